@@ -0,0 +1,1452 @@
+//! Collects snapshots from procfs, sysfs and the Pi firmware
+
+mod readers;
+
+use serde::Serialize;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
+    env, fs,
+    process::Command,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use sysinfo::{Disks, Networks, System};
+use tokio::{
+    sync::{watch, Semaphore},
+    time::{interval, MissedTickBehavior},
+};
+use tracing::warn;
+
+use crate::{
+    config::{Config, CoreUsageMode, CustomCommand, CustomPath, DiskAlertRule, NetworkNamespace},
+    snapshot::{
+        format_rfc3339, AveragingMethod, BootConfig, BootInfo, CollectionError, CoreUsageSummary,
+        CpuInfo, CpuVulnerability, DiskAlert, FirmwareInfo, FrequencyResidency, GpuMemoryInfo,
+        InterruptRate, LoggingInfo, MeasurementWindow, NetworkInterface, StorageDevice,
+        SystemSnapshot, ThrottleInfo, TimeSync, Voltages, SCHEMA_VERSION,
+    },
+};
+use readers::{
+    detect_environment, get_local_ip_addresses, get_pi_model, is_raspberry_pi_model, parse_get_mem,
+    read_boot_config, read_cooling_devices, read_core_frequencies, read_cpu_info,
+    read_cpu_temperature, read_cpu_vulnerabilities, read_critical_trip_point, read_custom_paths,
+    read_displays, read_failed_units, read_firmware_info, read_interrupt_counts,
+    read_journal_usage, read_mount_options, read_namespace_net_dev, read_one_wire_sensors,
+    read_online_cpu_count, read_oom_kill_count, read_poe_hat, read_previous_shutdown,
+    read_proc_net_dev, read_proc_uptime, read_serial_number, read_storage_device,
+    read_storage_log_warnings, read_sysfs, read_throttle_flags, read_time_in_state, read_time_sync,
+    read_usb, read_vcgencmd_throttle_flags, read_vm_settings, read_voltages, read_zram_devices,
+    vcgencmd,
+};
+
+// How far back journal growth is measured. journald allocates files in
+// chunks, so shorter windows mostly see steps.
+const JOURNAL_GROWTH_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+// Metrics that are too costly to gather on every snapshot. They are refreshed
+// on a slower schedule and merged into each snapshot as they were last seen.
+#[derive(Debug, Clone, Default)]
+pub struct ExpensiveMetrics {
+    local_ips: Vec<String>,
+    voltages: Option<Voltages>,
+    // Storage problems found in the kernel log; None when it can't be read
+    storage_log_warnings: Option<Vec<String>>,
+    time_sync: TimeSync,
+    failed_units: Option<Vec<String>>,
+    // Throttle flags from vcgencmd, only read when sysfs doesn't have them
+    throttle: Option<ThrottleInfo>,
+}
+
+// Data sources found at startup, so missing data can be explained
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    // Device-tree or cpuinfo model names a Raspberry Pi; without it the
+    // Pi-specific sections (throttling, voltages, firmware, PoE HAT) stay empty
+    raspberry_pi: bool,
+    procfs: bool,
+    sysfs_thermal: bool,
+    device_tree: bool,
+    vcgencmd: bool,
+    wtmp: bool,
+}
+
+impl Capabilities {
+    pub fn probe() -> Self {
+        let capabilities = Self {
+            raspberry_pi: get_pi_model().as_deref().is_some_and(is_raspberry_pi_model),
+            procfs: fs::metadata("/proc/stat").is_ok() && fs::metadata("/proc/meminfo").is_ok(),
+            sysfs_thermal: fs::read_dir("/sys/class/thermal").is_ok(),
+            device_tree: fs::metadata("/proc/device-tree/model").is_ok(),
+            vcgencmd: Command::new("vcgencmd")
+                .arg("version")
+                .output()
+                .is_ok_and(|output| output.status.success()),
+            wtmp: fs::File::open("/var/log/wtmp").is_ok(),
+        };
+
+        for warning in capabilities.warnings() {
+            warn!("{}", warning);
+        }
+
+        capabilities
+    }
+
+    // Explanations for data that will be missing because a source is absent
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.raspberry_pi {
+            warnings.push(
+                "Not running on a Raspberry Pi: throttling, voltage, firmware and PoE HAT \
+                 metrics will be empty, and CPU temperature only reads if the kernel \
+                 exposes a thermal zone",
+            );
+        }
+        if !self.procfs {
+            warnings.push("/proc is unavailable: CPU, memory and load metrics will read as zero");
+        }
+        if !self.sysfs_thermal && !self.vcgencmd {
+            warnings.push("No thermal zones or vcgencmd found: CPU temperature will read as zero");
+        }
+        if !self.wtmp {
+            warnings.push("/var/log/wtmp is unreadable: previous shutdown kind will be unknown");
+        }
+        warnings.into_iter().map(String::from).collect()
+    }
+}
+
+// Subscribe to the ambient temperature topic, publishing each reading with
+// the time it arrived. The client reconnects on its own after broker outages.
+#[cfg(feature = "mqtt")]
+pub fn subscribe_ambient_temperature(
+    config: &Config,
+    topic: &str,
+) -> anyhow::Result<watch::Receiver<Option<(f32, Instant)>>> {
+    use crate::snapshot::json_path_number;
+    use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+    use tracing::info;
+
+    let (host, port) = match config.mqtt_broker.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| anyhow::anyhow!("Invalid MQTT_BROKER port {:?}", port))?,
+        ),
+        None => (config.mqtt_broker.as_str(), 1883),
+    };
+    let client_id = format!("life_of_pi-{}-{}", config.source_name, std::process::id());
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+    let (client, mut event_loop) = AsyncClient::new(options, 10);
+
+    let (readings_tx, readings_rx) = watch::channel(None);
+    let topic = topic.to_string();
+    let field = config.mqtt_ambient_field.clone();
+    let broker = config.mqtt_broker.clone();
+    info!("Reading ambient temperature from MQTT topic {}", topic);
+    tokio::spawn(async move {
+        // Only the first failure of an outage is logged
+        let mut failing = false;
+        loop {
+            match event_loop.poll().await {
+                // Subscriptions don't survive a reconnect with a clean session
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    if failing {
+                        info!("Reconnected to MQTT broker {}", broker);
+                        failing = false;
+                    }
+                    if let Err(e) = client.try_subscribe(&topic, QoS::AtMostOnce) {
+                        warn!("Failed to subscribe to {}: {}", topic, e);
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let payload = String::from_utf8_lossy(&publish.payload);
+                    let celsius = payload.trim().parse::<f64>().ok().or_else(|| {
+                        let json = serde_json::from_str(&payload).ok()?;
+                        json_path_number(&json, &field)
+                    });
+                    match celsius {
+                        Some(celsius) => {
+                            readings_tx.send_replace(Some((celsius as f32, Instant::now())));
+                        }
+                        None => warn!("No temperature in MQTT payload on {}: {}", topic, payload),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if !failing {
+                        warn!("MQTT connection to {} failed: {}", broker, e);
+                        failing = true;
+                    }
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+    Ok(readings_rx)
+}
+
+// Gather the metrics that are refreshed on the slow schedule
+pub async fn collect_expensive_metrics(permits: &Semaphore) -> ExpensiveMetrics {
+    let (local_ips, voltages, storage_log_warnings, time_sync, failed_units, throttle) = tokio::join!(
+        // Addresses rarely change, and the shell fallbacks are slow
+        run_heavy(permits, get_local_ip_addresses),
+        // One `vcgencmd` call per rail
+        run_heavy(permits, read_voltages),
+        // Scans the whole kernel ring buffer
+        run_heavy(permits, read_storage_log_warnings),
+        // Asks chronyc, then timedatectl
+        run_heavy(permits, read_time_sync),
+        // One D-Bus round trip, no subprocess
+        read_failed_units(),
+        // Only when the sysfs node is missing
+        run_heavy(permits, read_vcgencmd_throttle_flags),
+    );
+    ExpensiveMetrics {
+        local_ips,
+        voltages,
+        storage_log_warnings,
+        time_sync,
+        failed_units,
+        throttle,
+    }
+}
+
+// Run a costly blocking reader once a permit is free, so enabling several of
+// them can't pile up into the load spikes we're trying to observe
+async fn run_heavy<T: Default + Send + 'static>(permits: &Semaphore, read: fn() -> T) -> T {
+    let Ok(_permit) = permits.acquire().await else {
+        return T::default();
+    };
+    tokio::task::spawn_blocking(read).await.unwrap_or_else(|e| {
+        warn!("Expensive metrics refresh failed: {}", e);
+        T::default()
+    })
+}
+
+// Collects snapshots, keeping sysinfo state between refreshes
+pub struct SystemCollector {
+    sys: System,
+    cpu_sample_window: Duration,
+    core_usage_mode: CoreUsageMode,
+    consistent_reads: bool,
+    profile_refresh: bool,
+    // Only warn once when the online CPU count and sysinfo's CPU list disagree
+    warned_core_mismatch: bool,
+    // Previous OOM kill counter reading, for the rate and new-event warnings
+    last_oom_kills: Option<(u64, Instant)>,
+    // Interrupt collection is opt-in; None when disabled
+    interrupts_top_n: Option<usize>,
+    last_interrupts: Option<(HashMap<String, u64>, Instant)>,
+    labels: BTreeMap<String, String>,
+    source: String,
+    // Identity that does not change at runtime, read once unless disabled
+    static_info: StaticInfo,
+    cache_static_info: bool,
+    collect_cooling_devices: bool,
+    collect_usb: bool,
+    collect_cpu: bool,
+    collect_memory: bool,
+    collect_storage: bool,
+    collect_network: bool,
+    collect_temperature: bool,
+    default_critical_temp: f32,
+    custom_paths: Vec<CustomPath>,
+    // Latest custom command values, fed by the background runner
+    pub custom_command_values: Option<watch::Receiver<BTreeMap<String, serde_json::Value>>>,
+    disk_alert_rules: Vec<DiskAlertRule>,
+    // Throttle session counters and when we last saw the throttle state
+    throttle_events: u64,
+    throttled_seconds: f64,
+    last_throttle_check: Option<(bool, Instant)>,
+    // Per-interface (rx, tx) byte counters when the monitor first saw them
+    network_baseline: HashMap<String, (u64, u64)>,
+    // Per-interface counters at the previous snapshot, for rates and idle time
+    network_samples: HashMap<String, InterfaceSample>,
+    network_namespaces: Vec<NetworkNamespace>,
+    // Namespaces we already warned about being unreadable
+    warned_namespaces: HashSet<String>,
+    // cpufreq time_in_state counters from the previous snapshot
+    last_time_in_state: Option<BTreeMap<u32, u64>>,
+    // When the previous snapshot was collected, for labelling rates
+    last_collected_at: Option<Instant>,
+    // Latest ambient reading and when it arrived, fed by the MQTT subscription
+    pub ambient: Option<watch::Receiver<Option<(f32, Instant)>>>,
+    ambient_max_age: Duration,
+    // Journal sizes within JOURNAL_GROWTH_WINDOW, oldest first
+    journal_sizes: VecDeque<(Instant, u64)>,
+    // Mounted filesystems, only enumerated while storage is collected
+    disks: Disks,
+    // Limits costly readers, shared with the expensive metrics refresh
+    pub heavy_permits: Arc<Semaphore>,
+    expensive_interval: Duration,
+    // Last GPU memory reading and when it was taken
+    gpu_memory: Option<(Option<GpuMemoryInfo>, Instant)>,
+}
+
+// Chooses which subsystems a SystemCollector reads; everything else comes
+// from the Config. A disabled subsystem skips its sysinfo refresh and leaves
+// its snapshot fields at their defaults.
+struct SystemCollectorBuilder<'a> {
+    config: &'a Config,
+    cpu: bool,
+    memory: bool,
+    storage: bool,
+    network: bool,
+    temperature: bool,
+}
+
+impl SystemCollectorBuilder<'_> {
+    fn with_cpu(mut self, enabled: bool) -> Self {
+        self.cpu = enabled;
+        self
+    }
+
+    fn with_memory(mut self, enabled: bool) -> Self {
+        self.memory = enabled;
+        self
+    }
+
+    // Without storage, disks are never enumerated
+    fn with_storage(mut self, enabled: bool) -> Self {
+        self.storage = enabled;
+        self
+    }
+
+    // Without network, interfaces are never enumerated
+    fn with_network(mut self, enabled: bool) -> Self {
+        self.network = enabled;
+        self
+    }
+
+    fn with_temperature(mut self, enabled: bool) -> Self {
+        self.temperature = enabled;
+        self
+    }
+
+    fn build(self) -> anyhow::Result<SystemCollector> {
+        if !(self.cpu || self.memory || self.storage || self.network || self.temperature) {
+            anyhow::bail!(
+                "Nothing to collect: enable at least one of COLLECT_CPU, COLLECT_MEMORY, \
+                 COLLECT_STORAGE, COLLECT_NETWORK and COLLECT_TEMPERATURE"
+            );
+        }
+        let config = self.config;
+        Ok(SystemCollector {
+            sys: System::new_all(),
+            cpu_sample_window: config.cpu_sample_window,
+            core_usage_mode: config.core_usage_mode,
+            consistent_reads: config.consistent_reads,
+            profile_refresh: config.profile_refresh,
+            warned_core_mismatch: false,
+            last_oom_kills: None,
+            interrupts_top_n: config.collect_interrupts.then_some(config.interrupts_top_n),
+            last_interrupts: None,
+            labels: config.labels.clone(),
+            source: config.source_name.clone(),
+            static_info: StaticInfo::read(self.storage),
+            cache_static_info: config.cache_static_info,
+            collect_cooling_devices: config.collect_cooling_devices,
+            collect_usb: config.collect_usb,
+            collect_cpu: self.cpu,
+            collect_memory: self.memory,
+            collect_storage: self.storage,
+            collect_network: self.network,
+            collect_temperature: self.temperature,
+            default_critical_temp: config.thermal_critical_temp,
+            custom_paths: config.custom_paths.clone(),
+            custom_command_values: None,
+            disk_alert_rules: config.disk_alerts.clone(),
+            throttle_events: 0,
+            throttled_seconds: 0.0,
+            last_throttle_check: None,
+            network_baseline: HashMap::new(),
+            network_samples: HashMap::new(),
+            network_namespaces: config.network_namespaces.clone(),
+            warned_namespaces: HashSet::new(),
+            last_time_in_state: None,
+            last_collected_at: None,
+            ambient: None,
+            ambient_max_age: config.mqtt_ambient_max_age,
+            journal_sizes: VecDeque::new(),
+            disks: Disks::new(),
+            heavy_permits: Arc::new(Semaphore::new(config.max_heavy_collectors)),
+            expensive_interval: config.expensive_interval,
+            gpu_memory: None,
+        })
+    }
+}
+
+// System identity and facts that only change across a reboot. Read when the
+// collector is built and reused between snapshots, unless CACHE_STATIC_INFO
+// is off.
+#[derive(Debug, Clone)]
+struct StaticInfo {
+    hostname: String,
+    os_name: String,
+    kernel_version: String,
+    current_user: String,
+    pi_model: Option<String>,
+    serial_number: Option<String>,
+    cpu_info: Option<CpuInfo>,
+    environment: &'static str,
+    // Mitigations are fixed at boot
+    cpu_security: Vec<CpuVulnerability>,
+    // Edits to config.txt only take effect after a reboot, so the file as it
+    // was at startup best matches what the firmware applied
+    boot_config: Option<BootConfig>,
+    boot_info: BootInfo,
+    firmware: Option<FirmwareInfo>,
+    // Only looked up while storage is collected
+    root_device: Option<StorageDevice>,
+}
+
+impl StaticInfo {
+    fn read(collect_storage: bool) -> Self {
+        Self {
+            hostname: System::host_name()
+                .or_else(|| read_sysfs("/proc/sys/kernel/hostname"))
+                .or_else(|| read_sysfs("/etc/hostname"))
+                .filter(|name: &String| !name.is_empty())
+                .unwrap_or_else(|| "unknown".to_string()),
+            os_name: System::long_os_version().unwrap_or_else(|| "Unknown OS".to_string()),
+            kernel_version: System::kernel_version().unwrap_or_else(|| "Unknown".to_string()),
+            current_user: env::var("USER").unwrap_or_else(|_| "unknown".to_string()),
+            pi_model: get_pi_model(),
+            serial_number: read_serial_number(),
+            cpu_info: read_cpu_info(),
+            environment: detect_environment(),
+            cpu_security: read_cpu_vulnerabilities(),
+            boot_config: read_boot_config(),
+            boot_info: BootInfo {
+                boot_time: System::boot_time(),
+                previous_shutdown: read_previous_shutdown(),
+            },
+            firmware: read_firmware_info(),
+            root_device: if collect_storage {
+                read_storage_device("/")
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl SystemCollector {
+    // Builder with every subsystem enabled
+    fn builder(config: &Config) -> SystemCollectorBuilder<'_> {
+        SystemCollectorBuilder {
+            config,
+            cpu: true,
+            memory: true,
+            storage: true,
+            network: true,
+            temperature: true,
+        }
+    }
+
+    // Collector reading the subsystems selected by the COLLECT_* settings
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        Self::builder(config)
+            .with_cpu(config.collect_cpu)
+            .with_memory(config.collect_memory)
+            .with_storage(config.collect_storage)
+            .with_network(config.collect_network)
+            .with_temperature(config.collect_temperature)
+            .build()
+    }
+
+    // Get current system metrics
+    // Sections that fail are reported in `collection_errors` and left at
+    // their defaults; only fails when nothing at all could be read
+    pub fn collect(&mut self, expensive: &ExpensiveMetrics) -> anyhow::Result<SystemSnapshot> {
+        if !self.cache_static_info {
+            self.static_info = StaticInfo::read(self.collect_storage);
+        }
+        let since_previous = self
+            .last_collected_at
+            .replace(Instant::now())
+            .map(|at| at.elapsed());
+        let mut collection_errors = Vec::new();
+        let mut section_failed = |section: &str, error: String| {
+            collection_errors.push(CollectionError {
+                section: section.to_string(),
+                error,
+            });
+        };
+
+        // Durations of the sysinfo refreshes, reported with PROFILE_REFRESH
+        let mut refresh_timings = BTreeMap::new();
+        let elapsed_ms = |start: Instant| start.elapsed().as_secs_f64() * 1000.0;
+
+        let sys = &mut self.sys;
+        let started = Instant::now();
+        if self.collect_cpu && self.collect_memory {
+            sys.refresh_all();
+            refresh_timings.insert("refresh_all".to_string(), elapsed_ms(started));
+        } else {
+            // refresh_all also walks every process; with a subsystem turned
+            // off, only refresh what is still reported
+            if self.collect_cpu {
+                sys.refresh_cpu_all();
+            }
+            if self.collect_memory {
+                sys.refresh_memory();
+            }
+            refresh_timings.insert("refresh_selected".to_string(), elapsed_ms(started));
+        }
+        let mut memory_read_at = Instant::now();
+
+        // CPU usage (global usage). sysinfo reports usage relative to the
+        // previous refresh, so take a second reading after the sample window
+        // to decouple measurement accuracy from the collection interval.
+        if self.collect_cpu && !self.cpu_sample_window.is_zero() {
+            std::thread::sleep(
+                self.cpu_sample_window
+                    .max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL),
+            );
+            let started = Instant::now();
+            sys.refresh_cpu_usage();
+            refresh_timings.insert("cpu".to_string(), elapsed_ms(started));
+        }
+        let cpu_read_at = Instant::now();
+        // Memory and load are read right after the CPU sample when asked, so
+        // they describe the same moment instead of the start of the window
+        if self.consistent_reads && self.collect_memory {
+            sys.refresh_memory();
+            memory_read_at = Instant::now();
+        }
+        let load_avg = System::load_average();
+        let load_read_at = Instant::now();
+        let collection_skew_ms = collection_skew_ms(&[
+            self.collect_memory.then_some(memory_read_at),
+            self.collect_cpu.then_some(cpu_read_at),
+            Some(load_read_at),
+        ]);
+        let (cpu_usage, per_core, cpu_cores) = if self.collect_cpu {
+            let per_core: Vec<f32> = sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect();
+            if per_core.is_empty() {
+                section_failed("cpu", "no CPUs reported".to_string());
+            }
+            // sysinfo fixes its CPU list at startup, so it misses hotplugged cores
+            let cpu_cores = read_online_cpu_count().unwrap_or(per_core.len());
+            (sys.global_cpu_usage(), per_core, cpu_cores)
+        } else {
+            Default::default()
+        };
+        if cpu_cores != per_core.len() && !self.warned_core_mismatch {
+            warn!(
+                "{} CPUs are online but usage is reported for {}",
+                cpu_cores,
+                per_core.len()
+            );
+            self.warned_core_mismatch = true;
+        }
+        let core_usage_summary = match self.core_usage_mode {
+            CoreUsageMode::Full => None,
+            CoreUsageMode::Summary | CoreUsageMode::Both => CoreUsageSummary::from_usage(&per_core),
+        };
+        let core_usage = match self.core_usage_mode {
+            CoreUsageMode::Summary => Vec::new(),
+            CoreUsageMode::Full | CoreUsageMode::Both => per_core,
+        };
+
+        // Memory
+        let (memory_total, memory_used, swap_total, swap_used) = if self.collect_memory {
+            if sys.total_memory() == 0 {
+                section_failed("memory", "total memory is unknown".to_string());
+            }
+            (
+                sys.total_memory(),
+                sys.used_memory(),
+                sys.total_swap(),
+                sys.used_swap(),
+            )
+        } else {
+            Default::default()
+        };
+        let memory_percent = if memory_total > 0 {
+            (memory_used as f32 / memory_total as f32) * 100.0
+        } else {
+            0.0
+        };
+        let (zram, memory_oom_kills, memory_oom_kills_per_minute, vm_settings) =
+            if self.collect_memory {
+                let memory_oom_kills = read_oom_kill_count();
+                (
+                    read_zram_devices(),
+                    memory_oom_kills,
+                    self.oom_kill_rate(memory_oom_kills),
+                    read_vm_settings(),
+                )
+            } else {
+                Default::default()
+            };
+
+        // Disk (use root filesystem)
+        let mut disk_total = 0;
+        let mut disk_used = 0;
+        if self.collect_storage {
+            let started = Instant::now();
+            self.disks.refresh_list();
+            refresh_timings.insert("disks".to_string(), elapsed_ms(started));
+        }
+        let disks = &self.disks;
+        for disk in disks {
+            if disk.mount_point().to_str().unwrap_or("") == "/" {
+                disk_total = disk.total_space();
+                disk_used = disk_total - disk.available_space();
+                break;
+            }
+        }
+        let disk_percent = if disk_total > 0 {
+            (disk_used as f32 / disk_total as f32) * 100.0
+        } else {
+            if self.collect_storage {
+                section_failed("disk", "root filesystem not found".to_string());
+            }
+            0.0
+        };
+        let (disk_mount_options, mut storage_health_warnings, disk_device) = if self.collect_storage
+        {
+            (
+                read_mount_options("/"),
+                expensive.storage_log_warnings.clone(),
+                self.static_info.root_device.clone(),
+            )
+        } else {
+            Default::default()
+        };
+        if disk_mount_options.iter().any(|option| option == "ro") {
+            storage_health_warnings
+                .get_or_insert_with(Vec::new)
+                .push("root filesystem is mounted read-only".to_string());
+        }
+        let storage_degraded = storage_health_warnings
+            .as_ref()
+            .map(|warnings| !warnings.is_empty());
+        let storage_health_warnings = storage_health_warnings.unwrap_or_default();
+        let disk_alerts = self
+            .disk_alert_rules
+            .iter()
+            .filter_map(|rule| {
+                let disk = disks
+                    .iter()
+                    .find(|disk| disk.mount_point() == std::path::Path::new(&rule.mount_point))?;
+                let total = disk.total_space();
+                let percent = if total > 0 {
+                    (total - disk.available_space()) as f32 / total as f32 * 100.0
+                } else {
+                    0.0
+                };
+                (percent > rule.max_percent).then(|| DiskAlert {
+                    mount_point: rule.mount_point.clone(),
+                    percent,
+                    max_percent: rule.max_percent,
+                })
+            })
+            .collect();
+        let logging = if self.collect_storage {
+            self.journal_usage()
+        } else {
+            None
+        };
+
+        // Network (sum all interfaces)
+        let mut network_rx = 0;
+        let mut network_tx = 0;
+        let mut network_interfaces = Vec::new();
+        let networks = if self.collect_network {
+            let started = Instant::now();
+            let networks = Networks::new_with_refreshed_list();
+            refresh_timings.insert("networks".to_string(), elapsed_ms(started));
+            if networks.list().is_empty() {
+                section_failed("network", "no network interfaces found".to_string());
+            }
+            networks
+        } else {
+            Networks::new()
+        };
+        let sampled_at = Instant::now();
+        let since_boot = if self.collect_network {
+            read_proc_net_dev()
+        } else {
+            HashMap::new()
+        };
+        for (name, network) in &networks {
+            let (rx, tx) = (network.total_received(), network.total_transmitted());
+            network_rx += rx;
+            network_tx += tx;
+            let boot_counters = since_boot.get(name).copied();
+            let &mut (baseline_rx, baseline_tx) = self
+                .network_baseline
+                .entry(name.clone())
+                .or_insert((rx, tx));
+            let sys_path = std::path::Path::new("/sys/class/net").join(name);
+            let (rx_bytes_per_sec, tx_bytes_per_sec, seconds_since_last_activity) =
+                sample_interface(
+                    &mut self.network_samples,
+                    name.clone(),
+                    (rx, tx),
+                    sampled_at,
+                );
+            network_interfaces.push(NetworkInterface {
+                name: name.clone(),
+                namespace: None,
+                rx_bytes: rx,
+                tx_bytes: tx,
+                rx_bytes_since_boot: boot_counters.map(|(rx, _)| rx),
+                tx_bytes_since_boot: boot_counters.map(|(_, tx)| tx),
+                rx_bytes_session: rx.saturating_sub(baseline_rx),
+                tx_bytes_session: tx.saturating_sub(baseline_tx),
+                mtu: read_sysfs(sys_path.join("mtu")),
+                tx_queue_len: read_sysfs(sys_path.join("tx_queue_len")),
+                addresses: network
+                    .ip_networks()
+                    .iter()
+                    .map(|net| net.addr.to_string())
+                    .collect(),
+                rx_bytes_per_sec,
+                tx_bytes_per_sec,
+                seconds_since_last_activity,
+            });
+        }
+        if self.collect_network {
+            for namespace in &self.network_namespaces {
+                let counters = match read_namespace_net_dev(namespace) {
+                    Ok(counters) => counters,
+                    Err(e) => {
+                        if self.warned_namespaces.insert(namespace.name.clone()) {
+                            warn!("Cannot read network namespace {}: {}", namespace.name, e);
+                        }
+                        continue;
+                    }
+                };
+                for (name, (rx, tx)) in counters {
+                    let key = format!("{}/{}", namespace.name, name);
+                    let &mut (baseline_rx, baseline_tx) =
+                        self.network_baseline.entry(key.clone()).or_insert((rx, tx));
+                    let (rx_bytes_per_sec, tx_bytes_per_sec, seconds_since_last_activity) =
+                        sample_interface(&mut self.network_samples, key, (rx, tx), sampled_at);
+                    network_interfaces.push(NetworkInterface {
+                        name,
+                        namespace: Some(namespace.name.clone()),
+                        rx_bytes: rx,
+                        tx_bytes: tx,
+                        rx_bytes_since_boot: Some(rx),
+                        tx_bytes_since_boot: Some(tx),
+                        rx_bytes_session: rx.saturating_sub(baseline_rx),
+                        tx_bytes_session: tx.saturating_sub(baseline_tx),
+                        rx_bytes_per_sec,
+                        tx_bytes_per_sec,
+                        seconds_since_last_activity,
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        // Host interfaces first, then each namespace
+        network_interfaces.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+
+        // CPU temperature (Raspberry Pi specific)
+        let (cpu_temp, thermal_critical_temp) = if self.collect_temperature {
+            let cpu_temp = read_cpu_temperature().unwrap_or_else(|e| {
+                section_failed("temperature", e.to_string());
+                0.0
+            });
+            let critical = read_critical_trip_point().unwrap_or(self.default_critical_temp);
+            (cpu_temp, critical)
+        } else {
+            Default::default()
+        };
+        let thermal_headroom_percent = (cpu_temp > 0.0 && thermal_critical_temp > 0.0).then(|| {
+            ((thermal_critical_temp - cpu_temp) / thermal_critical_temp * 100.0).clamp(0.0, 100.0)
+        });
+        let ambient_celsius = self.ambient.as_ref().and_then(|ambient| {
+            let (celsius, received_at) = (*ambient.borrow())?;
+            (received_at.elapsed() <= self.ambient_max_age).then_some(celsius)
+        });
+        // Sensors, fans and throttling belong to the temperature subsystem
+        let cooling_devices = if self.collect_temperature && self.collect_cooling_devices {
+            read_cooling_devices()
+        } else {
+            Vec::new()
+        };
+        let (external_sensors, throttle, poe_hat) = if self.collect_temperature {
+            (
+                read_one_wire_sensors(),
+                self.throttle_status(expensive.throttle.as_ref()),
+                read_poe_hat(),
+            )
+        } else {
+            Default::default()
+        };
+        let cpu_frequency_residency = if self.collect_cpu {
+            self.frequency_residency()
+        } else {
+            Vec::new()
+        };
+        let core_frequencies_mhz = if self.collect_cpu {
+            read_core_frequencies()
+        } else {
+            Vec::new()
+        };
+        let cpu_frequency_mhz = if self.collect_cpu {
+            read_sysfs::<u32>("/sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq")
+                .map(|khz| khz / 1000)
+        } else {
+            None
+        };
+
+        // System information
+        let StaticInfo {
+            hostname,
+            os_name,
+            kernel_version,
+            current_user,
+            pi_model,
+            serial_number,
+            cpu_info,
+            environment,
+            cpu_security,
+            boot_config,
+            boot_info,
+            firmware,
+            root_device: _,
+        } = self.static_info.clone();
+        let uptime = System::uptime();
+        let (idle_seconds, cpu_usage_since_boot) = match read_proc_uptime() {
+            Some((uptime_seconds, idle_seconds)) => {
+                let capacity = uptime_seconds * self.sys.cpus().len() as f64;
+                let usage = (capacity > 0.0)
+                    .then(|| ((1.0 - idle_seconds / capacity) * 100.0).clamp(0.0, 100.0) as f32);
+                (Some(idle_seconds), usage)
+            }
+            None => (None, None),
+        };
+        let local_ips = expensive.local_ips.clone();
+        let voltages = expensive.voltages.clone();
+        let gpu_memory = self.collect_gpu_memory();
+        let time_sync = expensive.time_sync.clone();
+        let failed_units = expensive.failed_units.clone();
+        let environment = environment.to_string();
+        let is_raspberry_pi = pi_model.as_deref().is_some_and(is_raspberry_pi_model);
+        let displays = read_displays();
+        let usb = self.collect_usb.then(read_usb);
+        let interrupts = self.interrupt_rates();
+        let mut custom = read_custom_paths(&self.custom_paths);
+        if let Some(values) = &self.custom_command_values {
+            custom.extend(values.borrow().clone());
+        }
+
+        use AveragingMethod::{Exponential, SincePrevious, Window};
+        let cpu_window = if self.cpu_sample_window.is_zero() {
+            MeasurementWindow::new(SincePrevious, since_previous)
+        } else {
+            let window = self
+                .cpu_sample_window
+                .max(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+            MeasurementWindow::new(Window, Some(window))
+        };
+        let mut measurement_windows = BTreeMap::from([
+            ("cpu_usage".to_string(), cpu_window.clone()),
+            ("core_usage".to_string(), cpu_window),
+            (
+                "load_avg_1m".to_string(),
+                MeasurementWindow::new(Exponential, Some(Duration::from_secs(60))),
+            ),
+            (
+                "load_avg_5m".to_string(),
+                MeasurementWindow::new(Exponential, Some(Duration::from_secs(5 * 60))),
+            ),
+            (
+                "load_avg_15m".to_string(),
+                MeasurementWindow::new(Exponential, Some(Duration::from_secs(15 * 60))),
+            ),
+            (
+                "memory_oom_kills_per_minute".to_string(),
+                MeasurementWindow::new(SincePrevious, since_previous),
+            ),
+        ]);
+        if !cpu_frequency_residency.is_empty() {
+            measurement_windows.insert(
+                "cpu_frequency_residency".to_string(),
+                MeasurementWindow::new(SincePrevious, since_previous),
+            );
+        }
+        if !interrupts.is_empty() {
+            measurement_windows.insert(
+                "interrupts[].per_second".to_string(),
+                MeasurementWindow::new(SincePrevious, since_previous),
+            );
+        }
+        if !network_interfaces.is_empty() {
+            measurement_windows.insert(
+                "network_interfaces[].rx_bytes_per_sec".to_string(),
+                MeasurementWindow::new(SincePrevious, since_previous),
+            );
+            measurement_windows.insert(
+                "network_interfaces[].tx_bytes_per_sec".to_string(),
+                MeasurementWindow::new(SincePrevious, since_previous),
+            );
+        }
+        if logging.is_some() {
+            // Up to this long, less while the monitor has just started
+            measurement_windows.insert(
+                "logging.journal_growth_bytes_per_hour".to_string(),
+                MeasurementWindow::new(Window, Some(JOURNAL_GROWTH_WINDOW)),
+            );
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        // Disabled sections never fail, so only count the ones that were read
+        let enabled_sections = [
+            self.collect_cpu,
+            self.collect_memory,
+            self.collect_storage,
+            self.collect_network,
+            self.collect_temperature,
+        ]
+        .into_iter()
+        .filter(|&enabled| enabled)
+        .count();
+        if collection_errors.len() == enabled_sections {
+            anyhow::bail!(
+                "Every section failed: {}",
+                collection_errors
+                    .iter()
+                    .map(|failure| format!("{}: {}", failure.section, failure.error))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            );
+        }
+
+        Ok(SystemSnapshot {
+            schema_version: SCHEMA_VERSION,
+            timestamp,
+            sequence: 0, // assigned by the collection task
+            source: self.source.clone(),
+            timestamp_rfc3339: format_rfc3339(timestamp),
+            actual_interval_ms: None,
+            falling_behind: false,
+            collection_skew_ms,
+            measurement_windows,
+            refresh_timings: if self.profile_refresh {
+                refresh_timings
+            } else {
+                BTreeMap::new()
+            },
+            cpu_usage,
+            cpu_cores,
+            core_usage,
+            core_usage_summary,
+            cpu_frequency_residency,
+            cpu_frequency_mhz,
+            core_frequencies_mhz,
+            cpu_temp,
+            ambient_celsius,
+            thermal_critical_temp,
+            thermal_headroom_percent,
+            cooling_devices,
+            external_sensors,
+            voltages,
+            gpu_memory,
+            throttle,
+            memory_total,
+            memory_used,
+            memory_percent,
+            swap_total,
+            swap_used,
+            zram,
+            memory_oom_kills,
+            memory_oom_kills_per_minute,
+            vm_settings,
+            disk_total,
+            disk_used,
+            disk_percent,
+            disk_mount_options,
+            storage_degraded,
+            storage_health_warnings,
+            disk_alerts,
+            logging,
+            disk_device,
+            network_rx,
+            network_tx,
+            network_interfaces,
+            hostname,
+            os_name,
+            kernel_version,
+            uptime,
+            time_sync,
+            failed_units,
+            idle_seconds,
+            cpu_usage_since_boot,
+            load_avg_1m: load_avg.one,
+            load_avg_5m: load_avg.five,
+            load_avg_15m: load_avg.fifteen,
+            current_user,
+            local_ips,
+            pi_model,
+            cpu_info,
+            serial_number,
+            environment,
+            is_raspberry_pi,
+            boot_info,
+            cpu_security,
+            boot_config,
+            poe_hat,
+            firmware,
+            displays,
+            usb,
+            interrupts,
+            labels: self.labels.clone(),
+            custom,
+            collection_errors,
+            truncated_sections: Vec::new(),
+        })
+    }
+
+    // Share of time spent at each CPU frequency since the previous call, from
+    // cpufreq's time_in_state. Empty on the first call and when the kernel is
+    // built without CONFIG_CPU_FREQ_STAT.
+    fn frequency_residency(&mut self) -> Vec<FrequencyResidency> {
+        let Some(current) = read_time_in_state() else {
+            return Vec::new();
+        };
+        let Some(last) = self.last_time_in_state.replace(current.clone()) else {
+            return Vec::new();
+        };
+        let deltas: Vec<(u32, u64)> = current
+            .iter()
+            .map(|(&khz, &time)| {
+                (
+                    khz,
+                    time.saturating_sub(last.get(&khz).copied().unwrap_or(0)),
+                )
+            })
+            .collect();
+        let total: u64 = deltas.iter().map(|&(_, delta)| delta).sum();
+        if total == 0 {
+            return Vec::new();
+        }
+        deltas
+            .into_iter()
+            .map(|(khz, delta)| FrequencyResidency {
+                frequency_mhz: khz / 1000,
+                percent: delta as f32 / total as f32 * 100.0,
+            })
+            .collect()
+    }
+
+    // Journal size plus its growth rate over the recent window
+    fn journal_usage(&mut self) -> Option<LoggingInfo> {
+        let Some((journal_dir, journal_bytes)) = read_journal_usage() else {
+            self.journal_sizes.clear();
+            return None;
+        };
+        let now = Instant::now();
+        while self
+            .journal_sizes
+            .front()
+            .is_some_and(|&(at, _)| now.duration_since(at) > JOURNAL_GROWTH_WINDOW)
+        {
+            self.journal_sizes.pop_front();
+        }
+        // Vacuuming shrinks the journal; that isn't negative growth worth reporting
+        let journal_growth_bytes_per_hour =
+            self.journal_sizes.front().and_then(|&(since, bytes)| {
+                let hours = now.duration_since(since).as_secs_f64() / 3600.0;
+                (hours > 0.0).then(|| journal_bytes.saturating_sub(bytes) as f64 / hours)
+            });
+        self.journal_sizes.push_back((now, journal_bytes));
+        Some(LoggingInfo {
+            journal_dir: journal_dir.to_string(),
+            journal_bytes,
+            journal_growth_bytes_per_hour,
+        })
+    }
+
+    // ARM/GPU memory split, or None without a working vcgencmd. Re-read at
+    // most once per EXPENSIVE_INTERVAL_SECS; the vcgencmd calls take a heavy
+    // collector permit, and the previous reading is kept while none is free.
+    fn collect_gpu_memory(&mut self) -> Option<GpuMemoryInfo> {
+        if let Some((reading, read_at)) = &self.gpu_memory {
+            if read_at.elapsed() < self.expensive_interval {
+                return reading.clone();
+            }
+        }
+        let Ok(_permit) = self.heavy_permits.try_acquire() else {
+            return self
+                .gpu_memory
+                .as_ref()
+                .and_then(|(reading, _)| reading.clone());
+        };
+        let get_mem = |region: &str| parse_get_mem(&vcgencmd(&["get_mem", region])?);
+        let reading = get_mem("arm")
+            .and_then(|arm_bytes| Some((arm_bytes, get_mem("gpu")?)))
+            .map(|(arm_bytes, gpu_bytes)| {
+                // "malloc" is what is still free out of "malloc_total"
+                let malloc_used_bytes = get_mem("malloc_total")
+                    .zip(get_mem("malloc"))
+                    .map(|(total, free)| total.saturating_sub(free));
+                GpuMemoryInfo {
+                    arm_bytes,
+                    gpu_bytes,
+                    malloc_used_bytes,
+                }
+            });
+        self.gpu_memory = Some((reading.clone(), Instant::now()));
+        reading
+    }
+
+    // OOM kills per minute since the previous snapshot, warning on new kills
+    fn oom_kill_rate(&mut self, count: Option<u64>) -> f64 {
+        let Some(count) = count else {
+            return 0.0;
+        };
+        let now = Instant::now();
+        let rate = match self.last_oom_kills {
+            Some((last_count, last_time)) => {
+                let new_kills = count.saturating_sub(last_count);
+                if new_kills > 0 {
+                    warn!(
+                        "OOM killer invoked {} time(s) since last snapshot",
+                        new_kills
+                    );
+                }
+                let minutes = now.duration_since(last_time).as_secs_f64() / 60.0;
+                if minutes > 0.0 {
+                    new_kills as f64 / minutes
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        };
+        self.last_oom_kills = Some((count, now));
+        rate
+    }
+
+    // Current throttle flags, accumulating how often and how long the CPU
+    // has been limited since the monitor started. `fallback` is the last
+    // vcgencmd reading, used when sysfs has none.
+    fn throttle_status(&mut self, fallback: Option<&ThrottleInfo>) -> Option<ThrottleInfo> {
+        let mut throttle = read_throttle_flags().or_else(|| fallback.cloned())?;
+        let now = Instant::now();
+        let limited = throttle.is_limited();
+        if let Some((was_limited, last_check)) = self.last_throttle_check {
+            if was_limited {
+                self.throttled_seconds += now.duration_since(last_check).as_secs_f64();
+            }
+            if limited && !was_limited {
+                self.throttle_events += 1;
+            }
+        } else if limited {
+            self.throttle_events += 1;
+        }
+        self.last_throttle_check = Some((limited, now));
+
+        throttle.throttle_events = self.throttle_events;
+        throttle.throttled_seconds = self.throttled_seconds;
+        Some(throttle)
+    }
+
+    // Top interrupt sources by rate since the previous snapshot
+    fn interrupt_rates(&mut self) -> Vec<InterruptRate> {
+        let Some(top_n) = self.interrupts_top_n else {
+            return Vec::new();
+        };
+        let Some(counts) = read_interrupt_counts() else {
+            return Vec::new();
+        };
+        let now = Instant::now();
+
+        let mut rates: Vec<InterruptRate> = counts
+            .iter()
+            .map(|(irq, (name, total))| {
+                let per_second = match &self.last_interrupts {
+                    Some((last_counts, last_time)) => {
+                        let elapsed = now.duration_since(*last_time).as_secs_f64();
+                        let previous = last_counts.get(irq).copied().unwrap_or(*total);
+                        if elapsed > 0.0 {
+                            total.saturating_sub(previous) as f64 / elapsed
+                        } else {
+                            0.0
+                        }
+                    }
+                    None => 0.0,
+                };
+                InterruptRate {
+                    irq: irq.clone(),
+                    name: name.clone(),
+                    total: *total,
+                    per_second,
+                }
+            })
+            .collect();
+        rates.sort_by(|a, b| {
+            b.per_second
+                .total_cmp(&a.per_second)
+                .then(b.total.cmp(&a.total))
+        });
+        rates.truncate(top_n);
+
+        let totals = counts
+            .into_iter()
+            .map(|(irq, (_, total))| (irq, total))
+            .collect();
+        self.last_interrupts = Some((totals, now));
+        rates
+    }
+}
+
+// Run user-configured commands in the background once per `period`, so a
+// slow command never holds up a snapshot. Snapshots report the latest values,
+// which start out null.
+pub fn spawn_custom_commands(
+    config: &Config,
+    period: Duration,
+) -> watch::Receiver<BTreeMap<String, serde_json::Value>> {
+    let commands = config.custom_commands.clone();
+    let timeout = config.custom_command_timeout;
+    let permits = Arc::new(Semaphore::new(config.custom_command_concurrency.max(1)));
+    let (values_tx, values_rx) = watch::channel(null_custom_values(&commands));
+    tokio::spawn(async move {
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let values = run_custom_commands(&commands, timeout, &permits).await;
+            if values_tx.send(values).is_err() {
+                return;
+            }
+        }
+    });
+    values_rx
+}
+
+fn null_custom_values(commands: &[CustomCommand]) -> BTreeMap<String, serde_json::Value> {
+    commands
+        .iter()
+        .map(|custom| (custom.name.clone(), serde_json::Value::Null))
+        .collect()
+}
+
+// Run every command once, at most as many at a time as there are `permits`,
+// and all within a single `timeout`. Commands still running at the deadline
+// are killed. Failures, timeouts and unparsable output are reported as null.
+async fn run_custom_commands(
+    commands: &[CustomCommand],
+    timeout: Duration,
+    permits: &Arc<Semaphore>,
+) -> BTreeMap<String, serde_json::Value> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut runs = tokio::task::JoinSet::new();
+    for custom in commands.iter().cloned() {
+        let permits = permits.clone();
+        runs.spawn(async move {
+            let output = tokio::time::timeout_at(deadline, async {
+                let _permit = permits.acquire().await.ok()?;
+                run_custom_command(&custom).await
+            })
+            .await
+            .ok()
+            .flatten();
+            let value = output.and_then(|output| custom.kind.parse(&output));
+            (custom.name, value)
+        });
+    }
+    let mut values = null_custom_values(commands);
+    while let Some(run) = runs.join_next().await {
+        if let Ok((name, Some(value))) = run {
+            values.insert(name, value);
+        }
+    }
+    values
+}
+
+// Run a command and collect its stdout. None unless it exits successfully.
+// Dropping the future kills the command and closes the pipe, so nothing is
+// left waiting on a grandchild that inherited stdout.
+async fn run_custom_command(custom: &CustomCommand) -> Option<String> {
+    let output = tokio::process::Command::new(&custom.program)
+        .args(&custom.args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// An interface's byte counters at the previous snapshot
+#[derive(Debug, Clone, Copy)]
+struct InterfaceSample {
+    counters: (u64, u64), // (rx, tx)
+    read_at: Instant,
+    // When either counter last moved, or when the interface was first seen
+    last_change: Instant,
+}
+
+// Milliseconds between the earliest and latest read. Sections that weren't
+// read (None) don't count towards the skew.
+pub fn collection_skew_ms(reads: &[Option<Instant>]) -> f64 {
+    let reads = reads.iter().flatten();
+    match (reads.clone().min(), reads.max()) {
+        (Some(first), Some(last)) => last.duration_since(*first).as_secs_f64() * 1000.0,
+        _ => 0.0,
+    }
+}
+
+// Record an interface's counters read at `now`, keyed by name (or
+// "namespace/name"), and return its (rx, tx) bytes per second since the
+// previous sample plus the seconds since its counters last moved. An
+// interface seen for the first time has rates of 0 and no idle time; from
+// then on idle time counts from when it was found, so one that never passes
+// traffic keeps growing. Counters that went backwards give a rate of 0.
+fn sample_interface(
+    samples: &mut HashMap<String, InterfaceSample>,
+    key: String,
+    counters: (u64, u64),
+    now: Instant,
+) -> (f64, f64, Option<f64>) {
+    let sample = match samples.entry(key) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            entry.insert(InterfaceSample {
+                counters,
+                read_at: now,
+                last_change: now,
+            });
+            return (0.0, 0.0, None);
+        }
+    };
+    let elapsed = now.duration_since(sample.read_at).as_secs_f64();
+    let rate = |current: u64, previous: u64| {
+        if elapsed > 0.0 {
+            current.saturating_sub(previous) as f64 / elapsed
+        } else {
+            0.0
+        }
+    };
+    let rx_rate = rate(counters.0, sample.counters.0);
+    let tx_rate = rate(counters.1, sample.counters.1);
+    if sample.counters != counters {
+        sample.last_change = now;
+    }
+    sample.counters = counters;
+    sample.read_at = now;
+    let idle = now.duration_since(sample.last_change).as_secs_f64();
+    (rx_rate, tx_rate, Some(idle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_only_collector_never_touches_disks() {
+        let config = Config::from_pairs(&[("CPU_SAMPLE_WINDOW_MS", "0")]);
+        let mut collector = SystemCollector::builder(&config)
+            .with_memory(false)
+            .with_storage(false)
+            .with_network(false)
+            .with_temperature(false)
+            .build()
+            .unwrap();
+        let snapshot = collector.collect(&ExpensiveMetrics::default()).unwrap();
+        assert!(collector.disks.list().is_empty());
+        assert_eq!(snapshot.disk_total, 0);
+        assert_eq!(snapshot.memory_total, 0);
+        assert!(snapshot.logging.is_none());
+        assert!(snapshot.external_sensors.is_empty());
+        assert!(snapshot.throttle.is_none());
+        assert!(snapshot.poe_hat.is_none());
+        assert!(snapshot.cooling_devices.is_empty());
+    }
+
+    #[test]
+    fn memory_only_collector_leaves_other_sections_empty() {
+        let config = Config::from_pairs(&[("CPU_SAMPLE_WINDOW_MS", "0")]);
+        let mut collector = SystemCollector::builder(&config)
+            .with_cpu(false)
+            .with_storage(false)
+            .with_network(false)
+            .with_temperature(false)
+            .build()
+            .unwrap();
+        let snapshot = collector.collect(&ExpensiveMetrics::default()).unwrap();
+        assert!(snapshot.core_usage.is_empty());
+        assert!(snapshot.core_frequencies_mhz.is_empty());
+        assert!(snapshot.cpu_frequency_residency.is_empty());
+        assert!(snapshot.network_interfaces.is_empty());
+        assert_eq!(snapshot.cpu_temp, 0.0);
+        assert!(!snapshot
+            .measurement_windows
+            .contains_key("network_interfaces[].rx_bytes_per_sec"));
+        assert!(snapshot.memory_total > 0);
+    }
+
+    #[test]
+    fn collector_needs_a_subsystem() {
+        let config = Config::from_pairs(&[]);
+        let built = SystemCollector::builder(&config)
+            .with_cpu(false)
+            .with_memory(false)
+            .with_storage(false)
+            .with_network(false)
+            .with_temperature(false)
+            .build();
+        assert!(built.is_err());
+    }
+
+    #[test]
+    fn skew_leaves_out_sections_not_read() {
+        let memory = Instant::now();
+        let cpu = memory + Duration::from_millis(500);
+        let load = cpu + Duration::from_millis(2);
+        assert_eq!(
+            collection_skew_ms(&[Some(memory), Some(cpu), Some(load)]),
+            502.0
+        );
+        // Memory off: its timestamp from before the CPU window is left out
+        assert_eq!(collection_skew_ms(&[None, Some(cpu), Some(load)]), 2.0);
+        assert_eq!(collection_skew_ms(&[None, None, Some(load)]), 0.0);
+    }
+
+    #[test]
+    fn interface_rates_from_two_samples() {
+        let mut samples = HashMap::new();
+        let start = Instant::now();
+        let first = sample_interface(&mut samples, "eth0".to_string(), (1_000, 500), start);
+        assert_eq!((first.0, first.1), (0.0, 0.0));
+
+        let later = start + Duration::from_secs(2);
+        let second = sample_interface(&mut samples, "eth0".to_string(), (5_000, 1_500), later);
+        assert_eq!((second.0, second.1), (2_000.0, 500.0));
+
+        // Interface recreated: counters restart below the previous reading
+        let reset = later + Duration::from_secs(2);
+        let third = sample_interface(&mut samples, "eth0".to_string(), (100, 1_900), reset);
+        assert_eq!((third.0, third.1), (0.0, 200.0));
+    }
+
+    #[test]
+    fn silent_interface_idle_time_counts_from_discovery() {
+        let mut samples = HashMap::new();
+        let start = Instant::now();
+        let first = sample_interface(&mut samples, "wlan0".to_string(), (42, 42), start);
+        assert_eq!(first.2, None);
+        let later = start + Duration::from_secs(10);
+        let second = sample_interface(&mut samples, "wlan0".to_string(), (42, 42), later);
+        assert_eq!(second.2, Some(10.0));
+    }
+}
@@ -0,0 +1,1279 @@
+//! Readers for individual data sources, each returning what it found
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::IpAddr,
+    process::Command,
+    str::FromStr,
+};
+use sysinfo::Networks;
+
+use crate::{
+    config::{CustomPath, NetworkNamespace},
+    snapshot::{
+        BootConfig, CoolingDevice, CoolingKind, CpuInfo, CpuVulnerability, DisplayInfo,
+        FirmwareInfo, PoeHatInfo, ShutdownKind, StorageDevice, ThrottleInfo, TimeDaemon, TimeSync,
+        UsbDevice, UsbInfo, VmSettings, Voltages, ZramDevice, BOOT_CONFIG_KEYS,
+    },
+};
+
+// Parse /proc/interrupts into irq -> (name, count summed across CPUs)
+pub fn read_interrupt_counts() -> Option<HashMap<String, (String, u64)>> {
+    let content = fs::read_to_string("/proc/interrupts").ok()?;
+    let mut lines = content.lines();
+    let cpu_count = lines.next()?.split_whitespace().count();
+
+    let mut counts = HashMap::new();
+    for line in lines {
+        let Some((irq, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let irq = irq.trim();
+        let mut fields = rest.split_whitespace().peekable();
+        let mut total = 0u64;
+        for _ in 0..cpu_count {
+            match fields.peek().and_then(|field| field.parse::<u64>().ok()) {
+                Some(count) => {
+                    total += count;
+                    fields.next();
+                }
+                None => break,
+            }
+        }
+        // Numbered IRQs end with the device name (e.g. "eth0", "mmc1");
+        // named ones (IPI0, ERR) are followed by a description
+        let description: Vec<&str> = fields.collect();
+        let name = if irq.chars().all(|c| c.is_ascii_digit()) {
+            description.last().copied().unwrap_or_default().to_string()
+        } else {
+            description.join(" ")
+        };
+        counts.insert(irq.to_string(), (name, total));
+    }
+    Some(counts)
+}
+
+// Sum the space taken by journald's files, preferring the persistent journal.
+// None when neither directory holds any, e.g. journald isn't running or only
+// forwards to syslog.
+pub fn read_journal_usage() -> Option<(&'static str, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    ["/var/log/journal", "/run/log/journal"]
+        .into_iter()
+        .find_map(|dir| {
+            // Files live in a per-machine-id subdirectory; archived and
+            // corrupted ones end in .journal~ and count towards the limit too
+            let mut files = 0;
+            let mut bytes = 0;
+            for machine in fs::read_dir(dir).ok()?.flatten() {
+                let Ok(entries) = fs::read_dir(machine.path()) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    if !name.ends_with(".journal") && !name.ends_with(".journal~") {
+                        continue;
+                    }
+                    if let Ok(metadata) = entry.metadata() {
+                        // Allocated blocks, since journald preallocates its files
+                        bytes += metadata.blocks() * 512;
+                        files += 1;
+                    }
+                }
+            }
+            (files > 0).then_some((dir, bytes))
+        })
+}
+
+// Read the cumulative OOM kill counter (kernel 4.13+)
+pub fn read_oom_kill_count() -> Option<u64> {
+    let vmstat = fs::read_to_string("/proc/vmstat").ok()?;
+    vmstat
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+// Read user-configured files, one failure doesn't affect the others
+pub fn read_custom_paths(paths: &[CustomPath]) -> BTreeMap<String, serde_json::Value> {
+    paths
+        .iter()
+        .map(|custom| {
+            let value = fs::read_to_string(&custom.path)
+                .ok()
+                .and_then(|text| custom.kind.parse(&text))
+                .unwrap_or(serde_json::Value::Null);
+            (custom.name.clone(), value)
+        })
+        .collect()
+}
+
+// Read (uptime, idle time summed across cores) in seconds from /proc/uptime
+pub fn read_proc_uptime() -> Option<(f64, f64)> {
+    let content = fs::read_to_string("/proc/uptime").ok()?;
+    let mut fields = content
+        .split_whitespace()
+        .map(|field| field.parse::<f64>().ok());
+    Some((fields.next()??, fields.next()??))
+}
+
+// Read DS18B20 temperature probes on the 1-Wire bus (family code 28)
+pub fn read_one_wire_sensors() -> BTreeMap<String, f32> {
+    let Ok(entries) = fs::read_dir("/sys/bus/w1/devices") else {
+        return BTreeMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let id = entry.file_name().to_string_lossy().into_owned();
+            if !id.starts_with("28-") {
+                return None;
+            }
+            // Two lines: the first ends in YES when the CRC matched, the
+            // second ends in t=<millidegrees>
+            //   72 01 4b 46 7f ff 0e 10 57 : crc=57 YES
+            //   72 01 4b 46 7f ff 0e 10 57 t=23125
+            let reading = fs::read_to_string(entry.path().join("w1_slave")).ok()?;
+            let mut lines = reading.lines();
+            if !lines.next()?.trim_end().ends_with("YES") {
+                return None;
+            }
+            let (_, millidegrees) = lines.next()?.rsplit_once("t=")?;
+            let celsius = millidegrees.trim().parse::<i32>().ok()? as f32 / 1000.0;
+            Some((id, celsius))
+        })
+        .collect()
+}
+
+const THROTTLED_SYSFS: &str = "/sys/devices/platform/soc/soc:firmware/get_throttled";
+
+// Read the firmware throttle flags from the firmware driver's sysfs node.
+// Cheap enough for every snapshot.
+pub fn read_throttle_flags() -> Option<ThrottleInfo> {
+    let raw = parse_throttled_hex(&fs::read_to_string(THROTTLED_SYSFS).ok()?)?;
+    Some(ThrottleInfo::from_flags(raw, "sysfs"))
+}
+
+// Ask vcgencmd for the throttle flags, for kernels without the sysfs node.
+// Runs on the expensive schedule, and not at all when the node exists.
+pub fn read_vcgencmd_throttle_flags() -> Option<ThrottleInfo> {
+    if std::path::Path::new(THROTTLED_SYSFS).exists() {
+        return None;
+    }
+    // Output looks like "throttled=0x50000"
+    let output = vcgencmd(&["get_throttled"])?;
+    let raw = parse_throttled_hex(output.trim().strip_prefix("throttled=")?)?;
+    Some(ThrottleInfo::from_flags(raw, "vcgencmd"))
+}
+
+fn parse_throttled_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text.trim().trim_start_matches("0x"), 16).ok()
+}
+
+// Read initialized zram devices and how well they compress
+pub fn read_zram_devices() -> Vec<ZramDevice> {
+    let Ok(entries) = fs::read_dir("/sys/block") else {
+        return Vec::new();
+    };
+    let swaps = fs::read_to_string("/proc/swaps").unwrap_or_default();
+    let mut devices: Vec<ZramDevice> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with("zram") {
+                return None;
+            }
+            let path = entry.path();
+            // Unconfigured devices have a zero size
+            let disk_size = read_sysfs::<u64>(path.join("disksize")).filter(|&size| size > 0)?;
+            // mm_stat: orig_data_size compr_data_size mem_used_total ...
+            let stats: Vec<u64> = fs::read_to_string(path.join("mm_stat"))
+                .ok()?
+                .split_whitespace()
+                .filter_map(|field| field.parse().ok())
+                .collect();
+            let (original_bytes, compressed_bytes, memory_used_bytes) =
+                (*stats.first()?, *stats.get(1)?, *stats.get(2)?);
+            // The active algorithm is bracketed: "lzo lzo-rle [lz4] zstd"
+            let algorithm = fs::read_to_string(path.join("comp_algorithm"))
+                .ok()
+                .and_then(|algorithms| {
+                    let (_, rest) = algorithms.split_once('[')?;
+                    Some(rest.split_once(']')?.0.to_string())
+                })
+                .unwrap_or_default();
+            let device = format!("/dev/{}", name);
+            Some(ZramDevice {
+                is_swap: swaps
+                    .lines()
+                    .any(|line| line.split_whitespace().next() == Some(device.as_str())),
+                name,
+                algorithm,
+                disk_size,
+                original_bytes,
+                compressed_bytes,
+                memory_used_bytes,
+                compression_ratio: (compressed_bytes > 0)
+                    .then(|| original_bytes as f32 / compressed_bytes as f32),
+            })
+        })
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+// Read swappiness and overcommit settings
+pub fn read_vm_settings() -> Option<VmSettings> {
+    let settings = VmSettings {
+        swappiness: read_sysfs("/proc/sys/vm/swappiness"),
+        overcommit_memory: read_sysfs("/proc/sys/vm/overcommit_memory"),
+        overcommit_ratio: read_sysfs("/proc/sys/vm/overcommit_ratio"),
+    };
+    let any_read = settings.swappiness.is_some()
+        || settings.overcommit_memory.is_some()
+        || settings.overcommit_ratio.is_some();
+    any_read.then_some(settings)
+}
+
+// Count online CPUs
+pub fn read_online_cpu_count() -> Option<usize> {
+    read_cpu_list("/sys/devices/system/cpu/online").map(|cpus| cpus.len())
+}
+
+// Read a CPU range list such as "0-3" or "0,2-3" into CPU numbers
+fn read_cpu_list(path: &str) -> Option<Vec<usize>> {
+    parse_cpu_list(&read_sysfs::<String>(path)?)
+}
+
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',') {
+        match range.split_once('-') {
+            Some((start, end)) => cpus.extend(start.parse::<usize>().ok()?..=end.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+// Read cumulative time per CPU frequency (kHz) from cpufreq stats. All cores
+// on a Pi share one clock, so cpu0 speaks for the whole CPU.
+pub fn read_time_in_state() -> Option<BTreeMap<u32, u64>> {
+    // One "<frequency kHz> <time in 10ms units>" pair per line
+    let contents =
+        fs::read_to_string("/sys/devices/system/cpu/cpu0/cpufreq/stats/time_in_state").ok()?;
+    let counters: BTreeMap<u32, u64> = contents
+        .lines()
+        .filter_map(|line| {
+            let (khz, time) = line.split_once(' ')?;
+            Some((khz.trim().parse().ok()?, time.trim().parse().ok()?))
+        })
+        .collect();
+    (!counters.is_empty()).then_some(counters)
+}
+
+// Read interface counters inside another network namespace. setns only
+// switches the calling thread, so it runs on a short-lived helper thread and
+// reads /proc/thread-self, leaving the rest of the process where it was.
+pub fn read_namespace_net_dev(
+    namespace: &NetworkNamespace,
+) -> anyhow::Result<HashMap<String, (u64, u64)>> {
+    let file = fs::File::open(&namespace.path)
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", namespace.path.display(), e))?;
+    std::thread::spawn(move || {
+        nix::sched::setns(&file, nix::sched::CloneFlags::CLONE_NEWNET)
+            .map_err(|e| anyhow::anyhow!("setns failed: {}", e))?;
+        Ok(parse_net_dev("/proc/thread-self/net/dev"))
+    })
+    .join()
+    .map_err(|_| anyhow::anyhow!("namespace reader thread panicked"))?
+}
+
+// Read per-interface (rx, tx) byte counters from /proc/net/dev
+pub fn read_proc_net_dev() -> HashMap<String, (u64, u64)> {
+    parse_net_dev("/proc/net/dev")
+}
+
+fn parse_net_dev(path: &str) -> HashMap<String, (u64, u64)> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    // After two header lines: "  eth0: <8 receive fields> <8 transmit fields>"
+    contents
+        .lines()
+        .skip(2)
+        .filter_map(|line| {
+            let (name, counters) = line.split_once(':')?;
+            let fields: Vec<u64> = counters
+                .split_whitespace()
+                .filter_map(|field| field.parse().ok())
+                .collect();
+            Some((name.trim().to_string(), (*fields.first()?, *fields.get(8)?)))
+        })
+        .collect()
+}
+
+// Read the options a mount point is mounted with from /proc/mounts. Matched by
+// mount point because device names differ (/dev/root vs /dev/mmcblk0p2).
+pub fn read_mount_options(mount_point: &str) -> Vec<String> {
+    let Ok(mounts) = fs::read_to_string("/proc/mounts") else {
+        return Vec::new();
+    };
+    // The last entry wins when something is mounted over an existing mount
+    mounts
+        .lines()
+        .rev()
+        .find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // Spaces in mount points are escaped as \040
+            let point = fields.get(1)?.replace("\\040", " ");
+            if point == mount_point {
+                fields.get(3).copied()
+            } else {
+                None
+            }
+        })
+        .map(|options| options.split(',').map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// Get local IP addresses, IPv4 first. Empty when none can be found, rather
+// than a misleading 127.0.0.1.
+pub fn get_local_ip_addresses() -> Vec<String> {
+    // Interface addresses from the kernel (getifaddrs), which also works on
+    // minimal images without `hostname` or `ip`
+    let networks = Networks::new_with_refreshed_list();
+    let mut addresses: Vec<IpAddr> = networks
+        .list()
+        .values()
+        .flat_map(|network| network.ip_networks().iter().map(|net| net.addr))
+        .filter(is_reportable_address)
+        .collect();
+    addresses.sort_by_key(|ip| (ip.is_ipv6(), *ip));
+    addresses.dedup();
+    if !addresses.is_empty() {
+        return addresses.iter().map(IpAddr::to_string).collect();
+    }
+    local_ip_addresses_from_commands()
+}
+
+// Addresses worth showing: not loopback, unspecified or link-local
+fn is_reportable_address(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ipv4) => !ipv4.is_loopback() && !ipv4.is_unspecified() && !ipv4.is_link_local(),
+        IpAddr::V6(ipv6) => {
+            !ipv6.is_loopback() && !ipv6.is_unspecified() && (ipv6.segments()[0] & 0xffc0) != 0xfe80
+        }
+    }
+}
+
+// Fallback for when interface addresses can't be listed
+fn local_ip_addresses_from_commands() -> Vec<String> {
+    let mut ips = Vec::new();
+
+    if let Ok(output) = Command::new("hostname").arg("-I").output() {
+        if output.status.success() {
+            let ip_string = String::from_utf8_lossy(&output.stdout);
+            for ip in ip_string.split_whitespace() {
+                if let Ok(parsed_ip) = ip.parse::<IpAddr>() {
+                    match parsed_ip {
+                        IpAddr::V4(ipv4) => {
+                            if !ipv4.is_loopback() && !ipv4.is_link_local() {
+                                ips.push(ip.to_string());
+                            }
+                        }
+                        IpAddr::V6(ipv6) => {
+                            if !ipv6.is_loopback() && !ipv6.is_unspecified() {
+                                ips.push(ip.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Fallback: try to get interface info from /proc/net/route and ifconfig
+    if ips.is_empty() {
+        if let Ok(output) = Command::new("ip")
+            .args(["route", "get", "8.8.8.8"])
+            .output()
+        {
+            if output.status.success() {
+                let route_info = String::from_utf8_lossy(&output.stdout);
+                // Parse "src <IP>" from the output
+                for line in route_info.lines() {
+                    if let Some(src_idx) = line.find("src ") {
+                        let ip_part = &line[src_idx + 4..];
+                        if let Some(ip_end) = ip_part.find(' ') {
+                            let ip = &ip_part[..ip_end];
+                            if ip.parse::<IpAddr>().is_ok() {
+                                ips.push(ip.to_string());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    ips
+}
+
+// Get Raspberry Pi model information
+pub fn get_pi_model() -> Option<String> {
+    // Try reading from /proc/device-tree/model first
+    if let Ok(model) = fs::read_to_string("/proc/device-tree/model") {
+        let cleaned = model.trim_end_matches('\0').trim();
+        if !cleaned.is_empty() {
+            return Some(cleaned.to_string());
+        }
+    }
+
+    // Fallback: read from /proc/cpuinfo
+    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+        for line in cpuinfo.lines() {
+            if line.starts_with("Model") {
+                if let Some(model) = line.split_once(':') {
+                    return Some(model.1.trim().to_string());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// Other single-board computers also have a device-tree model, e.g.
+// "Pine64 RockPro64 v2.1", so look for the name rather than any model at all
+pub fn is_raspberry_pi_model(model: &str) -> bool {
+    model.contains("Raspberry Pi")
+}
+
+// Find the disk a path lives on via its device number, which also works when
+// /proc/mounts only says /dev/root
+pub fn read_storage_device(path: &str) -> Option<StorageDevice> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = fs::metadata(path).ok()?.dev();
+    let (major, minor) = (nix::sys::stat::major(dev), nix::sys::stat::minor(dev));
+    let mut sys_path = fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)).ok()?;
+    // Partitions sit inside their disk's directory
+    if sys_path.join("partition").exists() {
+        sys_path.pop();
+    }
+    let name = sys_path.file_name()?.to_string_lossy().into_owned();
+
+    // SCSI/NVMe disks report a model, SD and eMMC cards a product name
+    let model = read_sysfs::<String>(sys_path.join("device/model"))
+        .or_else(|| read_sysfs(sys_path.join("device/name")))
+        .filter(|model| !model.is_empty());
+    let is_rotational = read_sysfs::<u8>(sys_path.join("queue/rotational")) == Some(1);
+    Some(StorageDevice {
+        name,
+        model,
+        is_rotational,
+    })
+}
+
+// Read CPU identification from the first core in /proc/cpuinfo
+pub fn read_cpu_info() -> Option<CpuInfo> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    // "key\t: value" lines; blocks per core are separated by blank lines
+    let first_core: HashMap<&str, &str> = cpuinfo
+        .split("\n\n")
+        .next()?
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim(), value.trim()))
+        })
+        .collect();
+    let field = |key: &str| first_core.get(key).map(|value| value.to_string());
+
+    let features = first_core
+        .get("Features")
+        .or_else(|| first_core.get("flags"))
+        .map(|flags| flags.split_whitespace().map(String::from).collect())
+        .unwrap_or_default();
+    let implementer = field("CPU implementer");
+    let part = field("CPU part");
+    let part_name = match (implementer.as_deref(), part.as_deref()) {
+        (Some("0x41"), Some(part)) => arm_part_name(part).map(String::from),
+        _ => None,
+    };
+    Some(CpuInfo {
+        features,
+        implementer,
+        part,
+        variant: field("CPU variant"),
+        revision: field("CPU revision"),
+        part_name,
+        available_frequencies_mhz: read_available_frequencies(),
+    })
+}
+
+// Read cpu0's frequency steps, given in kHz separated by spaces
+fn read_available_frequencies() -> Vec<u32> {
+    let Ok(contents) =
+        fs::read_to_string("/sys/devices/system/cpu/cpu0/cpufreq/scaling_available_frequencies")
+    else {
+        return Vec::new();
+    };
+    let mut frequencies: Vec<u32> = contents
+        .split_whitespace()
+        .filter_map(|khz| khz.parse::<u32>().ok())
+        .map(|khz| khz / 1000)
+        .collect();
+    frequencies.sort_unstable();
+    frequencies.dedup();
+    frequencies
+}
+
+// Arm core names for the part numbers found across Pi models
+fn arm_part_name(part: &str) -> Option<&'static str> {
+    match part {
+        "0xb76" => Some("ARM1176"),    // Pi 1, Zero
+        "0xc07" => Some("Cortex-A7"),  // Pi 2
+        "0xd03" => Some("Cortex-A53"), // Pi 3, Zero 2
+        "0xd08" => Some("Cortex-A72"), // Pi 4, 400, CM4
+        "0xd0b" => Some("Cortex-A76"), // Pi 5
+        _ => None,
+    }
+}
+
+// Read the board serial number, a stable per-device identifier
+pub fn read_serial_number() -> Option<String> {
+    // Device tree value is null-terminated
+    if let Ok(serial) = fs::read_to_string("/sys/firmware/devicetree/base/serial-number") {
+        let cleaned = serial.trim_end_matches('\0').trim();
+        if !cleaned.is_empty() {
+            return Some(cleaned.to_string());
+        }
+    }
+
+    // Fallback: read from /proc/cpuinfo
+    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+        for line in cpuinfo.lines() {
+            if line.starts_with("Serial") {
+                if let Some((_, serial)) = line.split_once(':') {
+                    let serial = serial.trim();
+                    if !serial.is_empty() {
+                        return Some(serial.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// Detect whether we run on bare metal, in a VM or in a container. CPU and
+// memory figures may be limited by the host in the latter two.
+pub fn detect_environment() -> &'static str {
+    // Container runtimes leave marker files behind
+    if fs::metadata("/.dockerenv").is_ok() {
+        return "docker";
+    }
+    if fs::metadata("/run/.containerenv").is_ok() {
+        return "podman";
+    }
+    if let Ok(cgroup) = fs::read_to_string("/proc/1/cgroup") {
+        if cgroup.contains("kubepods") {
+            return "kubernetes";
+        }
+        if cgroup.contains("docker") {
+            return "docker";
+        }
+        if cgroup.contains("lxc") {
+            return "lxc";
+        }
+    }
+
+    // systemd knows about most hypervisors and container managers
+    if let Ok(output) = Command::new("systemd-detect-virt").output() {
+        // Exits non-zero and prints "none" on bare metal
+        let virt = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if virt == "none" {
+            return "baremetal";
+        }
+        if !virt.is_empty() {
+            let is_container = Command::new("systemd-detect-virt")
+                .arg("--container")
+                .output()
+                .is_ok_and(|output| output.status.success());
+            return match virt.as_str() {
+                "docker" => "docker",
+                "podman" => "podman",
+                "lxc" | "lxc-libvirt" => "lxc",
+                _ if is_container => "container",
+                _ => "vm",
+            };
+        }
+    }
+
+    // x86 guests advertise the hypervisor CPU flag; Pis never do
+    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
+        if cpuinfo.lines().any(|line| {
+            line.starts_with("flags") && line.split_whitespace().any(|f| f == "hypervisor")
+        }) {
+            return "vm";
+        }
+        if fs::metadata("/proc/device-tree/model").is_ok() {
+            return "baremetal";
+        }
+    }
+
+    "unknown"
+}
+
+// Read the kernel's status for each known CPU vulnerability
+pub fn read_cpu_vulnerabilities() -> Vec<CpuVulnerability> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/cpu/vulnerabilities") else {
+        return Vec::new();
+    };
+    let mut vulnerabilities: Vec<CpuVulnerability> = entries
+        .flatten()
+        .filter_map(|entry| {
+            Some(CpuVulnerability {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                status: read_sysfs(entry.path())?,
+            })
+        })
+        .collect();
+    vulnerabilities.sort_by(|a, b| a.name.cmp(&b.name));
+    vulnerabilities
+}
+
+// Read the overclock and memory settings from config.txt
+pub fn read_boot_config() -> Option<BootConfig> {
+    // Bookworm moved the boot partition to /boot/firmware
+    let (path, contents) = ["/boot/firmware/config.txt", "/boot/config.txt"]
+        .into_iter()
+        .find_map(|path| Some((path, fs::read_to_string(path).ok()?)))?;
+
+    // Lines are "key=value" with # comments. Conditional sections such as
+    // [pi4] are not evaluated, so a later setting wins regardless of section.
+    let settings = contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.split('#').next()?.trim();
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            BOOT_CONFIG_KEYS
+                .contains(&key)
+                .then(|| (key.to_string(), value.trim().to_string()))
+        })
+        .collect();
+    Some(BootConfig {
+        path: path.to_string(),
+        settings,
+    })
+}
+
+// Determine whether the session before the current boot shut down cleanly
+pub fn read_previous_shutdown() -> ShutdownKind {
+    // `last -x` lists reboot/shutdown records from wtmp, newest first
+    let output = match Command::new("last")
+        .args(["-x", "reboot", "shutdown"])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return ShutdownKind::Unknown,
+    };
+    let records = String::from_utf8_lossy(&output.stdout);
+    let mut kinds = records
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|kind| *kind == "reboot" || *kind == "shutdown");
+
+    // The first record is the current boot; the one before it tells us how
+    // the previous session ended. A reboot without a shutdown in between
+    // means power loss or a crash.
+    match (kinds.next(), kinds.next()) {
+        (Some("reboot"), Some("shutdown")) => ShutdownKind::Clean,
+        (Some("reboot"), Some("reboot")) => ShutdownKind::Unexpected,
+        _ => ShutdownKind::Unknown,
+    }
+}
+
+// Read all thermal cooling devices and their current level
+pub fn read_cooling_devices() -> Vec<CoolingDevice> {
+    let Ok(entries) = fs::read_dir("/sys/class/thermal") else {
+        return Vec::new();
+    };
+    let mut devices: Vec<CoolingDevice> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with("cooling_device") {
+                return None;
+            }
+            let path = entry.path();
+            let device_type = fs::read_to_string(path.join("type"))
+                .ok()?
+                .trim()
+                .to_string();
+            let kind = CoolingKind::from_device_type(&device_type);
+            let cur_state: u32 = read_sysfs(path.join("cur_state"))?;
+            let max_state: u32 = read_sysfs(path.join("max_state"))?;
+            let (mut duty_percent, mut rpm) = (None, None);
+            if kind == CoolingKind::Fan {
+                let (pwm, fan_rpm) = read_fan_hwmon(&path);
+                // pwm1 runs from 0 to 255
+                duty_percent = pwm
+                    .map(|pwm| f32::from(pwm.min(255)) / 255.0 * 100.0)
+                    .or_else(|| {
+                        (max_state > 0).then(|| cur_state as f32 / max_state as f32 * 100.0)
+                    });
+                rpm = fan_rpm;
+            }
+            Some(CoolingDevice {
+                kind,
+                cur_state,
+                max_state,
+                duty_percent,
+                rpm,
+                device_type,
+                name,
+            })
+        })
+        .collect();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+// Read (pwm1, fan1_input) from the hwmon device of a fan's cooling device,
+// e.g. the Pi 5 active cooler's cooling_fan/hwmon/hwmonN
+fn read_fan_hwmon(cooling_device: &std::path::Path) -> (Option<u16>, Option<u32>) {
+    let Ok(entries) = fs::read_dir(cooling_device.join("device/hwmon")) else {
+        return (None, None);
+    };
+    entries
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            (
+                read_sysfs(path.join("pwm1")),
+                read_sysfs(path.join("fan1_input")),
+            )
+        })
+        .find(|(pwm, rpm)| pwm.is_some() || rpm.is_some())
+        .unwrap_or((None, None))
+}
+
+// Read connection state of each DRM display connector
+pub fn read_displays() -> Vec<DisplayInfo> {
+    let Ok(entries) = fs::read_dir("/sys/class/drm") else {
+        return Vec::new();
+    };
+    let mut displays: Vec<DisplayInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            // Connectors are named cardN-<type>-<index>; cardN itself is the GPU
+            let connector = entry.file_name().to_string_lossy().into_owned();
+            if !connector.starts_with("card") || !connector.contains('-') {
+                return None;
+            }
+            let path = entry.path();
+            let status = fs::read_to_string(path.join("status")).ok()?;
+            let connected = status.trim() == "connected";
+            let enabled = fs::read_to_string(path.join("enabled"))
+                .is_ok_and(|enabled| enabled.trim() == "enabled");
+            // The first listed mode is the display's preferred one
+            let resolution = connected
+                .then(|| fs::read_to_string(path.join("modes")).ok())
+                .flatten()
+                .and_then(|modes| modes.lines().next().map(str::to_string));
+            Some(DisplayInfo {
+                connector,
+                connected,
+                enabled,
+                resolution,
+            })
+        })
+        .collect();
+    displays.sort_by(|a, b| a.connector.cmp(&b.connector));
+    displays
+}
+
+// Enumerate USB devices and read the port power state
+pub fn read_usb() -> UsbInfo {
+    let Ok(entries) = fs::read_dir("/sys/bus/usb/devices") else {
+        return UsbInfo::default();
+    };
+    let mut devices = Vec::new();
+    let mut over_current_count = None;
+    for entry in entries.flatten() {
+        let bus_id = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        // Hub ports sit below the hub's interface, e.g. 1-0:1.0/usb1-port1
+        if bus_id.contains(':') {
+            for port in fs::read_dir(&path).into_iter().flatten().flatten() {
+                if port.file_name().to_string_lossy().contains("-port") {
+                    if let Some(count) = read_sysfs::<u64>(port.path().join("over_current_count")) {
+                        *over_current_count.get_or_insert(0) += count;
+                    }
+                }
+            }
+            continue;
+        }
+        // usbN are the controllers' root hubs
+        if bus_id.starts_with("usb") {
+            continue;
+        }
+        let Some(vendor_id) = read_sysfs::<String>(path.join("idVendor")) else {
+            continue;
+        };
+        devices.push(UsbDevice {
+            vendor_id,
+            product_id: read_sysfs(path.join("idProduct")).unwrap_or_default(),
+            manufacturer: read_sysfs(path.join("manufacturer")),
+            product: read_sysfs(path.join("product")),
+            speed_mbps: read_sysfs(path.join("speed")),
+            // e.g. "500mA"
+            max_power_ma: read_sysfs::<String>(path.join("bMaxPower"))
+                .and_then(|power| power.trim_end_matches("mA").parse().ok()),
+            bus_id,
+        });
+    }
+    devices.sort_by(|a, b| a.bus_id.cmp(&b.bus_id));
+
+    // Device tree cells are big-endian u32s
+    let max_current_enabled = fs::read("/proc/device-tree/chosen/power/usb_max_current_enable")
+        .ok()
+        .and_then(|bytes| Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?) != 0));
+    UsbInfo {
+        devices,
+        over_current_count,
+        max_current_enabled,
+    }
+}
+
+// Codecs the VideoCore can decode in hardware, some needing a licence key
+const VIDEO_CODECS: [&str; 7] = ["H264", "HEVC", "MPG2", "MPG4", "MJPG", "WVC1", "WMV9"];
+
+// Read the firmware build and enabled codecs from vcgencmd
+pub fn read_firmware_info() -> Option<FirmwareInfo> {
+    // Output looks like:
+    //   Mar 17 2023 10:50:39
+    //   Copyright (c) 2012 Broadcom
+    //   version 82f3750a65fadae9a38077e3c2e217ad158c8d54 (clean) (release) (start)
+    let output = vcgencmd(&["version"])?;
+    let mut lines = output.lines().map(str::trim);
+    let build_date = lines.next().unwrap_or_default().to_string();
+    let version = lines
+        .find_map(|line| line.strip_prefix("version "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap_or_default()
+        .to_string();
+
+    // Only Pi 4 and later have an EEPROM bootloader; first line is its date
+    let bootloader_version = vcgencmd(&["bootloader_version"])
+        .and_then(|output| output.lines().next().map(|line| line.trim().to_string()));
+
+    // Each query prints e.g. "H264=enabled"
+    let codecs_enabled = VIDEO_CODECS
+        .iter()
+        .filter(|codec| {
+            vcgencmd(&["codec_enabled", codec])
+                .is_some_and(|output| output.trim().ends_with("=enabled"))
+        })
+        .map(|codec| codec.to_string())
+        .collect();
+
+    Some(FirmwareInfo {
+        version,
+        build_date,
+        bootloader_version,
+        codecs_enabled,
+    })
+}
+
+// Run vcgencmd, returning its output when it succeeds
+pub fn vcgencmd(args: &[&str]) -> Option<String> {
+    let output = Command::new("vcgencmd").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Parse `vcgencmd get_mem` output such as "gpu=76M" into bytes. Sizes come
+// with a K, M or G suffix, or none for plain bytes.
+pub fn parse_get_mem(output: &str) -> Option<u64> {
+    let (_, size) = output.trim().split_once('=')?;
+    let (digits, multiplier) = match size.as_bytes().last()? {
+        b'K' | b'k' => (&size[..size.len() - 1], 1 << 10),
+        b'M' | b'm' => (&size[..size.len() - 1], 1 << 20),
+        b'G' | b'g' => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    digits.parse::<u64>().ok().map(|n| n * multiplier)
+}
+
+// Isolated I/O errors happen on hot-unplug or a marginal cable; below this
+// many per device they aren't treated as a failing disk
+const STORAGE_IO_ERROR_THRESHOLD: usize = 3;
+
+// Scan the kernel log for signs of a failing SD card or disk
+pub fn read_storage_log_warnings() -> Option<Vec<String>> {
+    let output = Command::new("dmesg").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(storage_log_warnings(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+pub fn storage_log_warnings(kernel_log: &str) -> Vec<String> {
+    // SCSI/USB disks are "sda", "sdab1"; a plain prefix match would also
+    // catch driver names such as "sdhci"
+    let is_scsi_disk = |word: &str| {
+        let Some(rest) = word.strip_prefix("sd") else {
+            return false;
+        };
+        let letters = rest.chars().take_while(char::is_ascii_lowercase).count();
+        (1..=2).contains(&letters) && rest[letters..].chars().all(|c| c.is_ascii_digit())
+    };
+    // Only physical storage; loop, ram, zram and optical devices are noise
+    let storage_device = |line: &str| {
+        line.split(|c: char| !c.is_ascii_alphanumeric())
+            .find(|word| {
+                word.starts_with("mmcblk") || word.starts_with("nvme") || is_scsi_disk(word)
+            })
+            .map(str::to_string)
+    };
+
+    let mut io_errors: BTreeMap<String, usize> = BTreeMap::new();
+    let mut filesystem_errors: BTreeMap<String, usize> = BTreeMap::new();
+    let mut remounted_read_only = Vec::new();
+    for line in kernel_log.lines() {
+        let Some(device) = storage_device(line) else {
+            continue;
+        };
+        if line.contains("Remounting filesystem read-only") {
+            remounted_read_only.push(device);
+        } else if line.contains("-fs error") {
+            // e.g. "EXT4-fs error (device mmcblk0p2): ..."
+            *filesystem_errors.entry(device).or_default() += 1;
+        } else if line.contains("I/O error") || line.contains("error -110") {
+            // "I/O error, dev mmcblk0, sector ..." and SD card timeouts
+            *io_errors.entry(device).or_default() += 1;
+        }
+    }
+
+    let mut warnings = Vec::new();
+    remounted_read_only.sort();
+    remounted_read_only.dedup();
+    for device in remounted_read_only {
+        warnings.push(format!("{} was remounted read-only after errors", device));
+    }
+    for (device, count) in filesystem_errors {
+        warnings.push(format!("{} filesystem error(s) on {}", count, device));
+    }
+    for (device, count) in io_errors {
+        if count >= STORAGE_IO_ERROR_THRESHOLD {
+            warnings.push(format!("{} I/O error(s) on {}", count, device));
+        }
+    }
+    warnings
+}
+
+// Name, description, load/active/sub state, followed unit, object path and
+// queued job (id, type, path) of each unit returned by ListUnitsByStates
+#[cfg(feature = "systemd")]
+type SystemdUnit = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    zbus::zvariant::OwnedObjectPath,
+    u32,
+    String,
+    zbus::zvariant::OwnedObjectPath,
+);
+
+// Ask systemd over the system bus which units have failed. None when there is
+// no systemd or bus to ask, e.g. in a container or on a non-systemd distro.
+#[cfg(feature = "systemd")]
+pub async fn read_failed_units() -> Option<Vec<String>> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tracing::warn;
+
+    let units = async {
+        let connection = zbus::Connection::system().await?;
+        let reply = connection
+            .call_method(
+                Some("org.freedesktop.systemd1"),
+                "/org/freedesktop/systemd1",
+                Some("org.freedesktop.systemd1.Manager"),
+                "ListUnitsByStates",
+                &(vec!["failed"],),
+            )
+            .await?;
+        reply.body().deserialize::<Vec<SystemdUnit>>()
+    }
+    .await;
+    match units {
+        Ok(units) => {
+            let mut names: Vec<String> = units.into_iter().map(|unit| unit.0).collect();
+            names.sort();
+            Some(names)
+        }
+        Err(e) => {
+            // Logged once: a missing bus won't appear between refreshes
+            static WARNED: AtomicBool = AtomicBool::new(false);
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                warn!("Failed to list failed systemd units: {}", e);
+            }
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "systemd"))]
+pub async fn read_failed_units() -> Option<Vec<String>> {
+    None
+}
+
+// Ask the running time daemon whether the clock is synchronized
+pub fn read_time_sync() -> TimeSync {
+    let run = |program: &str, args: &[&str]| {
+        let output = Command::new(program).args(args).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    // chronyc fails when chronyd isn't running. Relevant lines:
+    //   System time     : 0.000012345 seconds fast of NTP time
+    //   Leap status     : Normal
+    if let Some(tracking) = run("chronyc", &["tracking"]) {
+        let field = |name: &str| {
+            tracking.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                (key.trim() == name).then(|| value.trim())
+            })
+        };
+        let offset_ms = field("System time").and_then(|value| {
+            let mut words = value.split_whitespace();
+            let seconds: f64 = words.next()?.parse().ok()?;
+            let sign = match words.nth(1)? {
+                "fast" => 1.0,
+                "slow" => -1.0,
+                _ => return None,
+            };
+            Some(sign * seconds * 1000.0)
+        });
+        return TimeSync {
+            daemon: TimeDaemon::Chrony,
+            synchronized: field("Leap status").map(|status| status != "Not synchronised"),
+            offset_ms,
+        };
+    }
+
+    // systemd-timesyncd only says whether the clock is synchronized
+    let timesyncd_active = run("systemctl", &["is-active", "systemd-timesyncd"])
+        .is_some_and(|state| state.trim() == "active");
+    if timesyncd_active {
+        let synchronized = run("timedatectl", &["show", "-p", "NTPSynchronized", "--value"])
+            .map(|value| value.trim() == "yes");
+        return TimeSync {
+            daemon: TimeDaemon::Timesyncd,
+            synchronized,
+            offset_ms: None,
+        };
+    }
+
+    TimeSync::default()
+}
+
+// Read the core and SDRAM rail voltages, or None without a working vcgencmd
+pub fn read_voltages() -> Option<Voltages> {
+    // Each query prints e.g. "volt=1.2000V"
+    let measure = |rail: &str| {
+        vcgencmd(&["measure_volts", rail])?
+            .trim()
+            .strip_prefix("volt=")?
+            .trim_end_matches('V')
+            .parse::<f32>()
+            .ok()
+    };
+    let voltages = Voltages {
+        core: measure("core"),
+        sdram_c: measure("sdram_c"),
+        sdram_i: measure("sdram_i"),
+        sdram_p: measure("sdram_p"),
+    };
+    let any = voltages.core.is_some()
+        || voltages.sdram_c.is_some()
+        || voltages.sdram_i.is_some()
+        || voltages.sdram_p.is_some();
+    any.then_some(voltages)
+}
+
+// Read the PoE HAT fan (a thermal cooling device) and power supply
+pub fn read_poe_hat() -> Option<PoeHatInfo> {
+    let poe_fan = read_cooling_devices()
+        .into_iter()
+        .find(|device| device.device_type == "rpi-poe-fan");
+    let fan_state = poe_fan.as_ref().map(|fan| fan.cur_state);
+    let fan_max_state = poe_fan.as_ref().map(|fan| fan.max_state);
+
+    // The PoE+ HAT registers a power supply; current_max is in microamps
+    let supply = std::path::Path::new("/sys/class/power_supply/rpi-poe");
+    let power_online = read_sysfs::<u8>(supply.join("online")).map(|online| online == 1);
+    let current_max_ma = read_sysfs::<u32>(supply.join("current_max")).map(|ua| ua / 1000);
+
+    if fan_state.is_none() && power_online.is_none() && current_max_ma.is_none() {
+        return None;
+    }
+    Some(PoeHatInfo {
+        fan_state,
+        fan_max_state,
+        power_online,
+        current_max_ma,
+    })
+}
+
+// Read and parse a single-value sysfs/procfs file
+pub fn read_sysfs<T: FromStr>(path: impl AsRef<std::path::Path>) -> Option<T> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+// Current clock in MHz of every present core, indexed by CPU number. Offline
+// cores and cores without cpufreq read 0, so positions still line up with
+// core numbers when the online set has gaps (e.g. "0,2-3").
+pub fn read_core_frequencies() -> Vec<u32> {
+    let Some(present) = read_cpu_list("/sys/devices/system/cpu/present") else {
+        return Vec::new();
+    };
+    let online = read_cpu_list("/sys/devices/system/cpu/online").unwrap_or_default();
+    let cores = present.iter().max().map_or(0, |&last| last + 1);
+    (0..cores)
+        .map(|core| {
+            if !online.contains(&core) {
+                return 0;
+            }
+            let path = format!(
+                "/sys/devices/system/cpu/cpu{}/cpufreq/scaling_cur_freq",
+                core
+            );
+            read_sysfs::<u32>(path).map_or(0, |khz| khz / 1000)
+        })
+        .collect()
+}
+
+// Read the critical trip point of the CPU thermal zone in °C, where the
+// kernel shuts the system down
+pub fn read_critical_trip_point() -> Option<f32> {
+    let zone = std::path::Path::new("/sys/class/thermal/thermal_zone0");
+    (0..)
+        .map_while(|n| {
+            let kind = read_sysfs::<String>(zone.join(format!("trip_point_{}_type", n)))?;
+            Some((n, kind))
+        })
+        .find(|(_, kind)| kind == "critical")
+        .and_then(|(n, _)| read_sysfs::<i32>(zone.join(format!("trip_point_{}_temp", n))))
+        .map(|millidegrees| millidegrees as f32 / 1000.0)
+}
+
+// Read CPU temperature from Raspberry Pi thermal zone
+pub fn read_cpu_temperature() -> Result<f32, std::io::Error> {
+    // Pi-specific temperature paths in order of preference
+    let temp_paths = [
+        "/sys/class/thermal/thermal_zone0/temp", // Most common
+        "/sys/devices/virtual/thermal/thermal_zone0/temp", // Alternative path
+        "/sys/class/hwmon/hwmon0/temp1_input",   // Hardware monitor
+        "/sys/class/hwmon/hwmon1/temp1_input",   // Secondary hwmon
+    ];
+
+    // Try Pi-specific paths first
+    for path in &temp_paths {
+        if let Ok(temp_str) = fs::read_to_string(path) {
+            if let Ok(temp_millidegrees) = temp_str.trim().parse::<i32>() {
+                let temp_celsius = temp_millidegrees as f32 / 1000.0;
+                // Sanity check: temperature should be reasonable (0-100°C)
+                if temp_celsius > 0.0 && temp_celsius < 100.0 {
+                    return Ok(temp_celsius);
+                }
+            }
+        }
+    }
+
+    // Try vcgencmd (Raspberry Pi specific)
+    if let Ok(output) = Command::new("vcgencmd").arg("measure_temp").output() {
+        if output.status.success() {
+            let temp_output = String::from_utf8_lossy(&output.stdout);
+            // Parse "temp=XX.X'C" format
+            if let Some(start) = temp_output.find("temp=") {
+                let temp_part = &temp_output[start + 5..];
+                if let Some(end) = temp_part.find("'") {
+                    let temp_str = &temp_part[..end];
+                    if let Ok(temp) = temp_str.parse::<f32>() {
+                        if temp > 0.0 && temp < 100.0 {
+                            return Ok(temp);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Final fallback: try other thermal zones
+    for i in 0..10 {
+        let path = format!("/sys/class/thermal/thermal_zone{}/temp", i);
+        if let Ok(temp_str) = fs::read_to_string(&path) {
+            if let Ok(temp_millidegrees) = temp_str.trim().parse::<i32>() {
+                let temp_celsius = temp_millidegrees as f32 / 1000.0;
+                if temp_celsius > 0.0 && temp_celsius < 100.0 {
+                    return Ok(temp_celsius);
+                }
+            }
+        }
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "No valid thermal zone found",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_lists_expand_ranges() {
+        assert_eq!(parse_cpu_list("0-3\n"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(parse_cpu_list("0,2-3"), Some(vec![0, 2, 3]));
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[test]
+    fn get_mem_sizes_are_parsed_into_bytes() {
+        assert_eq!(parse_get_mem("arm=948M\n"), Some(948 << 20));
+        assert_eq!(parse_get_mem("gpu=76M"), Some(76 << 20));
+        assert_eq!(parse_get_mem("malloc=12K"), Some(12 << 10));
+        assert_eq!(parse_get_mem("malloc_total=1G"), Some(1 << 30));
+        assert_eq!(parse_get_mem("reloc=512"), Some(512));
+        assert_eq!(parse_get_mem("gpu="), None);
+        assert_eq!(parse_get_mem("gpu=M"), None);
+        assert_eq!(parse_get_mem("gpu=76MB"), None);
+        assert_eq!(
+            parse_get_mem("error=-2 error_msg=\"Command not registered\""),
+            None
+        );
+        assert_eq!(parse_get_mem(""), None);
+    }
+}
@@ -0,0 +1,880 @@
+//! Runtime configuration, read from environment variables
+
+use serde::Serialize;
+use std::{collections::BTreeMap, env, fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+use tokio::time::MissedTickBehavior;
+
+use crate::snapshot::{RedactedField, SystemSnapshot, REDACTED};
+
+// Limits beyond which /api/health reports "unhealthy"
+#[derive(Debug, Clone)]
+pub struct HealthThresholds {
+    max_cpu_temp: f32,     // °C
+    max_disk_percent: f32, // root filesystem
+    fail_on_read_only_root: bool,
+    // Journal growth in MiB per hour; 0 disables the check
+    max_journal_growth_mib_per_hour: f64,
+}
+
+impl HealthThresholds {
+    // Describe every check the snapshot fails
+    pub fn evaluate(&self, snapshot: &SystemSnapshot) -> Vec<String> {
+        let mut failing = Vec::new();
+        if snapshot.cpu_temp > self.max_cpu_temp {
+            failing.push(format!(
+                "cpu_temp {:.1}°C exceeds {:.1}°C",
+                snapshot.cpu_temp, self.max_cpu_temp
+            ));
+        }
+        if snapshot.disk_percent > self.max_disk_percent {
+            failing.push(format!(
+                "disk_percent {:.1}% exceeds {:.1}%",
+                snapshot.disk_percent, self.max_disk_percent
+            ));
+        }
+        if self.fail_on_read_only_root && snapshot.disk_mount_options.iter().any(|o| o == "ro") {
+            failing.push("root filesystem is mounted read-only".to_string());
+        }
+        let journal_growth = snapshot
+            .logging
+            .as_ref()
+            .and_then(|logging| logging.journal_growth_bytes_per_hour);
+        if let Some(growth) = journal_growth {
+            let mib_per_hour = growth / (1024.0 * 1024.0);
+            let limit = self.max_journal_growth_mib_per_hour;
+            if limit > 0.0 && mib_per_hour > limit {
+                failing.push(format!(
+                    "journal growing {:.1} MiB/h exceeds {:.1} MiB/h",
+                    mib_per_hour, limit
+                ));
+            }
+        }
+        for alert in &snapshot.disk_alerts {
+            failing.push(format!(
+                "{} usage {:.1}% exceeds {:.1}%",
+                alert.mount_point, alert.percent, alert.max_percent
+            ));
+        }
+        failing
+    }
+}
+
+// Runtime configuration, read from environment variables
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub port: u16,
+    // Where to listen instead when `port` is taken
+    pub port_fallback: PortFallback,
+    pub history_size: usize,
+    pub expensive_interval: Duration,
+    // How many costly readers (shell-outs, log scans) may run at once
+    pub max_heavy_collectors: usize,
+    // Serve the dashboard from this directory instead of the embedded copy
+    pub static_dir: Option<PathBuf>,
+    // Quiet period after the last change to STATIC_DIR/index.html before it
+    // is reloaded, so a save made of several writes is picked up once
+    pub static_reload_debounce: Duration,
+    // How long CPU usage is measured over; zero measures since the last snapshot
+    pub cpu_sample_window: Duration,
+    pub core_usage_mode: CoreUsageMode,
+    // Read memory and load right after the CPU sample instead of before it
+    pub consistent_reads: bool,
+    // Report how long each sysinfo refresh takes
+    pub profile_refresh: bool,
+    pub collect_interrupts: bool,
+    pub collect_usb: bool,
+    // Subsystems read on every snapshot; a disabled one keeps its snapshot
+    // fields at their defaults and skips its sysinfo refresh
+    pub collect_cpu: bool,
+    pub collect_memory: bool,
+    pub collect_storage: bool,
+    pub collect_network: bool,
+    pub collect_temperature: bool,
+    pub interrupts_top_n: usize,
+    pub labels: BTreeMap<String, String>,
+    // Tells snapshots from this monitor apart when several sources are merged
+    pub source_name: String,
+    // Prefix of every Prometheus metric name
+    pub metrics_prefix: String,
+    // `instance` label on /metrics; None uses the hostname
+    pub instance_label: Option<String>,
+    // Read hostname, OS and model once instead of on every snapshot
+    pub cache_static_info: bool,
+    // Sensitive fields masked in API and stream responses
+    pub redact: Vec<RedactedField>,
+    // Path prefix all routes are served under, e.g. "/pi-monitor"; empty for the root
+    pub base_path: String,
+    // Only listen on this network interface's address (e.g. a VPN tunnel)
+    pub bind_interface: Option<String>,
+    pub collect_cooling_devices: bool,
+    // Used for thermal headroom when the thermal zone has no critical trip point
+    pub thermal_critical_temp: f32,
+    // Extra sysfs/procfs files read on every snapshot
+    pub custom_paths: Vec<CustomPath>,
+    // Commands run on every snapshot, their output reported under `custom`
+    pub custom_commands: Vec<CustomCommand>,
+    pub custom_command_timeout: Duration,
+    // How many custom commands may run at once
+    pub custom_command_concurrency: usize,
+    // Other network namespaces to read interface counters from
+    pub network_namespaces: Vec<NetworkNamespace>,
+    // Per-mount usage limits; mounts over their limit fail /api/health
+    pub disk_alerts: Vec<DiskAlertRule>,
+    // What the collection ticker does after a slow collection
+    pub missed_tick_behavior: MissedTickBehavior,
+    // Collect less often while the 1-minute load exceeds this many times the core count
+    pub load_backoff: bool,
+    pub load_backoff_threshold: f64,
+    // Randomly lengthen or shorten each collection interval by up to this
+    // percentage, so a fleet started together doesn't report in lockstep
+    pub collection_jitter_percent: f64,
+    // Collect on wall-clock multiples of the interval (:00, :02, ...) so a
+    // fleet's snapshots line up
+    pub align_to_wall_clock: bool,
+    pub health_thresholds: HealthThresholds,
+    // Shell command run after each collection, e.g. to refresh an e-ink display
+    pub on_snapshot_command: Option<String>,
+    pub on_snapshot_timeout: Duration,
+    // Minimum time between hook runs
+    pub on_snapshot_min_interval: Duration,
+    // Pipe the snapshot JSON to the hook's stdin
+    pub on_snapshot_stdin: bool,
+    // Where SIGUSR1/SIGUSR2 dumps are written; logged when unset
+    pub signal_dump_dir: Option<PathBuf>,
+    // Only notify streaming clients when a snapshot materially changes
+    pub broadcast_only_changes: bool,
+    pub broadcast_tolerance: f64,
+    // Notify at least this often even without changes, so clients see the link is alive
+    pub broadcast_keepalive: Duration,
+    // Serve the effective configuration at /api/config
+    pub expose_config: bool,
+    // Bearer token for the control endpoints, which are off without one
+    pub control_token: Option<String>,
+    // Ambient temperature sensor publishing to an MQTT broker
+    pub mqtt_broker: String, // host or host:port
+    pub mqtt_ambient_topic: Option<String>,
+    // Dotted path to the temperature in JSON payloads; plain numbers are used as is
+    pub mqtt_ambient_field: String,
+    // Readings older than this are reported as missing
+    pub mqtt_ambient_max_age: Duration,
+    // Largest snapshot, as JSON, sent to clients; 0 for no limit
+    pub max_snapshot_bytes: usize,
+    // Failed collections in a row after which collection stops and event
+    // streams end; 0 to keep retrying forever
+    pub max_consecutive_failures: u32,
+}
+
+// Network namespace from NETWORK_NAMESPACES
+#[derive(Debug, Clone)]
+pub struct NetworkNamespace {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl NetworkNamespace {
+    // Names refer to `ip netns` namespaces in /run/netns; anything with a
+    // slash is a path such as /proc/<pid>/ns/net
+    fn parse(entry: &str) -> Self {
+        let path = if entry.contains('/') {
+            PathBuf::from(entry)
+        } else {
+            PathBuf::from("/run/netns").join(entry)
+        };
+        Self {
+            name: entry.to_string(),
+            path,
+        }
+    }
+}
+
+// Usage limit for one mount point, from DISK_ALERTS
+#[derive(Debug, Clone)]
+pub struct DiskAlertRule {
+    pub mount_point: String,
+    pub max_percent: f32,
+}
+
+// Parse `/mount=percent,/mount=percent` disk alert rules
+fn parse_disk_alerts(spec: &str) -> anyhow::Result<Vec<DiskAlertRule>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (mount_point, percent) = entry.rsplit_once('=').ok_or_else(|| {
+                anyhow::anyhow!("Invalid disk alert {:?}: expected /mount=percent", entry)
+            })?;
+            let mount_point = mount_point.trim();
+            if !mount_point.starts_with('/') {
+                anyhow::bail!(
+                    "Invalid disk alert {:?}: mount point must be an absolute path",
+                    entry
+                );
+            }
+            let max_percent = percent.trim().parse().map_err(|_| {
+                anyhow::anyhow!("Invalid disk alert {:?}: percent must be a number", entry)
+            })?;
+            Ok(DiskAlertRule {
+                mount_point: mount_point.to_string(),
+                max_percent,
+            })
+        })
+        .collect()
+}
+
+// A user-configured file whose contents are reported under `custom`
+#[derive(Debug, Clone)]
+pub struct CustomPath {
+    pub name: String,
+    pub kind: ValueKind,
+    pub path: PathBuf,
+}
+
+// A user-configured command whose output is reported under `custom`. It is
+// started directly, without a shell.
+#[derive(Debug, Clone)]
+pub struct CustomCommand {
+    pub name: String,
+    pub kind: ValueKind,
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+// How a custom value is parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Int,
+    Float,
+    String,
+}
+
+impl FromStr for ValueKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "int" => Ok(Self::Int),
+            "float" => Ok(Self::Float),
+            "string" => Ok(Self::String),
+            _ => anyhow::bail!("Unknown value type {:?}: expected int, float or string", s),
+        }
+    }
+}
+
+impl ValueKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::String => "string",
+        }
+    }
+
+    // Parse trimmed text into a JSON value, None if it doesn't fit the kind
+    pub fn parse(self, text: &str) -> Option<serde_json::Value> {
+        let text = text.trim();
+        match self {
+            Self::Int => text.parse::<i64>().ok().map(Into::into),
+            Self::Float => text
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(serde_json::Value::Number),
+            Self::String => Some(text.into()),
+        }
+    }
+}
+
+// Parse the ticker's catch-up policy. `burst` collects back-to-back to make up
+// for missed ticks, `delay` keeps even spacing from the late tick and `skip`
+// stays on the original schedule, dropping missed ticks.
+fn parse_missed_tick_behavior(s: &str) -> anyhow::Result<MissedTickBehavior> {
+    match s {
+        "burst" => Ok(MissedTickBehavior::Burst),
+        "delay" => Ok(MissedTickBehavior::Delay),
+        "skip" => Ok(MissedTickBehavior::Skip),
+        _ => anyhow::bail!(
+            "Invalid MISSED_TICK_BEHAVIOR {:?}: expected burst, delay or skip",
+            s
+        ),
+    }
+}
+
+// Parse `name=kind:/path,name=kind:/path` custom path entries
+fn parse_custom_paths(spec: &str) -> anyhow::Result<Vec<CustomPath>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, rest) = entry.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!("Invalid custom path {:?}: expected name=kind:/path", entry)
+            })?;
+            let (kind, path) = rest.split_once(':').ok_or_else(|| {
+                anyhow::anyhow!("Invalid custom path {:?}: expected name=kind:/path", entry)
+            })?;
+            Ok(CustomPath {
+                name: name.trim().to_string(),
+                kind: kind.trim().parse()?,
+                path: PathBuf::from(path.trim()),
+            })
+        })
+        .collect()
+}
+
+// Parse `name=kind:program args;name=kind:program args` custom commands.
+// Entries are separated by semicolons since arguments may contain commas.
+fn parse_custom_commands(spec: &str) -> anyhow::Result<Vec<CustomCommand>> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let invalid = || {
+                anyhow::anyhow!(
+                    "Invalid custom command {:?}: expected name=kind:command",
+                    entry
+                )
+            };
+            let (name, rest) = entry.split_once('=').ok_or_else(invalid)?;
+            let (kind, command) = rest.split_once(':').ok_or_else(invalid)?;
+            let mut words = command.split_whitespace().map(String::from);
+            let program = words.next().ok_or_else(invalid)?;
+            Ok(CustomCommand {
+                name: name.trim().to_string(),
+                kind: kind.trim().parse()?,
+                program,
+                args: words.collect(),
+            })
+        })
+        .collect()
+}
+
+// Normalize BASE_PATH to "" or "/prefix" without a trailing slash. The value is
+// also written into the dashboard's script, so only plain path characters are allowed.
+fn parse_base_path(value: &str) -> anyhow::Result<String> {
+    let trimmed = value.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if !trimmed
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+    {
+        anyhow::bail!(
+            "Invalid BASE_PATH {:?}: use letters, digits, '-', '_', '.' and '/'",
+            value
+        );
+    }
+    Ok(format!("/{}", trimmed))
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::from_vars(|name| env::var(name))
+    }
+
+    // Read the settings through `var`, so tests can pass explicit values
+    // instead of depending on the process environment
+    fn from_vars(var: impl Fn(&str) -> Result<String, env::VarError>) -> anyhow::Result<Self> {
+        let config = Self {
+            port: env_or(&var, "PORT", 8080)?,
+            port_fallback: env_or(&var, "PORT_FALLBACK", PortFallback::Strict)?,
+            history_size: env_or(&var, "HISTORY_SIZE", 300)?,
+            expensive_interval: Duration::from_secs(env_or(&var, "EXPENSIVE_INTERVAL_SECS", 30)?),
+            max_heavy_collectors: match env_or(&var, "MAX_HEAVY_COLLECTORS", 1)? {
+                0 => anyhow::bail!("MAX_HEAVY_COLLECTORS must be at least 1"),
+                limit => limit,
+            },
+            static_dir: var("STATIC_DIR").ok().map(PathBuf::from),
+            static_reload_debounce: Duration::from_millis(env_or(
+                &var,
+                "STATIC_RELOAD_DEBOUNCE_MS",
+                200,
+            )?),
+            cpu_sample_window: Duration::from_millis(env_or(&var, "CPU_SAMPLE_WINDOW_MS", 0)?),
+            core_usage_mode: var("CORE_USAGE")
+                .unwrap_or_else(|_| "full".to_string())
+                .parse()?,
+            consistent_reads: env_or(&var, "CONSISTENT_READS", false)?,
+            profile_refresh: env_or(&var, "PROFILE_REFRESH", false)?,
+            collect_interrupts: env_or(&var, "COLLECT_INTERRUPTS", false)?,
+            collect_usb: env_or(&var, "COLLECT_USB", false)?,
+            collect_cpu: env_or(&var, "COLLECT_CPU", true)?,
+            collect_memory: env_or(&var, "COLLECT_MEMORY", true)?,
+            collect_storage: env_or(&var, "COLLECT_STORAGE", true)?,
+            collect_network: env_or(&var, "COLLECT_NETWORK", true)?,
+            collect_temperature: env_or(&var, "COLLECT_TEMPERATURE", true)?,
+            interrupts_top_n: env_or(&var, "INTERRUPTS_TOP_N", 10)?,
+            labels: parse_labels(&var("LABELS").unwrap_or_default())?,
+            source_name: var("SOURCE_NAME")
+                .ok()
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "system".to_string()),
+            metrics_prefix: parse_metrics_prefix(
+                &var("METRICS_PREFIX").unwrap_or_else(|_| "lop".to_string()),
+            )?,
+            instance_label: var("INSTANCE_LABEL")
+                .ok()
+                .map(|label| label.trim().to_string())
+                .filter(|label| !label.is_empty()),
+            cache_static_info: env_or(&var, "CACHE_STATIC_INFO", true)?,
+            redact: var("REDACT_FIELDS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .map(str::parse)
+                .collect::<anyhow::Result<_>>()?,
+            base_path: parse_base_path(&var("BASE_PATH").unwrap_or_default())?,
+            bind_interface: var("BIND_INTERFACE").ok().filter(|name| !name.is_empty()),
+            collect_cooling_devices: env_or(&var, "COLLECT_COOLING_DEVICES", true)?,
+            thermal_critical_temp: env_or(&var, "THERMAL_CRITICAL_TEMP", 85.0)?,
+            custom_paths: parse_custom_paths(&var("CUSTOM_PATHS").unwrap_or_default())?,
+            custom_commands: parse_custom_commands(&var("CUSTOM_COMMANDS").unwrap_or_default())?,
+            custom_command_timeout: Duration::from_millis(env_or(
+                &var,
+                "CUSTOM_COMMAND_TIMEOUT_MS",
+                1000,
+            )?),
+            custom_command_concurrency: match env_or(&var, "CUSTOM_COMMAND_CONCURRENCY", 2)? {
+                0 => anyhow::bail!("CUSTOM_COMMAND_CONCURRENCY must be at least 1"),
+                limit => limit,
+            },
+            network_namespaces: var("NETWORK_NAMESPACES")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(NetworkNamespace::parse)
+                .collect(),
+            disk_alerts: parse_disk_alerts(&var("DISK_ALERTS").unwrap_or_default())?,
+            missed_tick_behavior: parse_missed_tick_behavior(
+                &var("MISSED_TICK_BEHAVIOR").unwrap_or_else(|_| "delay".to_string()),
+            )?,
+            load_backoff: env_or(&var, "LOAD_BACKOFF", false)?,
+            load_backoff_threshold: env_or(&var, "LOAD_BACKOFF_THRESHOLD", 2.0)?,
+            collection_jitter_percent: match env_or(&var, "COLLECTION_JITTER_PERCENT", 0.0)? {
+                percent if (0.0..100.0).contains(&percent) => percent,
+                percent => anyhow::bail!(
+                    "COLLECTION_JITTER_PERCENT must be at least 0 and below 100, got {}",
+                    percent
+                ),
+            },
+            align_to_wall_clock: env_or(&var, "ALIGN_TO_WALL_CLOCK", false)?,
+            health_thresholds: HealthThresholds {
+                max_cpu_temp: env_or(&var, "HEALTH_MAX_CPU_TEMP", 85.0)?,
+                max_disk_percent: env_or(&var, "HEALTH_MAX_DISK_PERCENT", 95.0)?,
+                fail_on_read_only_root: env_or(&var, "HEALTH_FAIL_ON_READ_ONLY_ROOT", true)?,
+                max_journal_growth_mib_per_hour: env_or(
+                    &var,
+                    "HEALTH_MAX_JOURNAL_GROWTH_MIB_PER_HOUR",
+                    0.0,
+                )?,
+            },
+            on_snapshot_command: var("ON_SNAPSHOT_COMMAND")
+                .ok()
+                .filter(|command| !command.is_empty()),
+            on_snapshot_timeout: Duration::from_secs(env_or(&var, "ON_SNAPSHOT_TIMEOUT_SECS", 10)?),
+            on_snapshot_min_interval: Duration::from_secs(env_or(
+                &var,
+                "ON_SNAPSHOT_MIN_INTERVAL_SECS",
+                0,
+            )?),
+            on_snapshot_stdin: env_or(&var, "ON_SNAPSHOT_STDIN", true)?,
+            signal_dump_dir: var("SIGNAL_DUMP_DIR").ok().map(PathBuf::from),
+            broadcast_only_changes: env_or(&var, "BROADCAST_ONLY_CHANGES", false)?,
+            broadcast_tolerance: env_or(&var, "BROADCAST_TOLERANCE", 0.5)?,
+            broadcast_keepalive: Duration::from_secs(env_or(&var, "BROADCAST_KEEPALIVE_SECS", 30)?),
+            expose_config: env_or(&var, "EXPOSE_CONFIG", false)?,
+            control_token: var("CONTROL_TOKEN").ok().filter(|token| !token.is_empty()),
+            mqtt_broker: var("MQTT_BROKER").unwrap_or_else(|_| "localhost:1883".to_string()),
+            mqtt_ambient_topic: var("MQTT_AMBIENT_TOPIC")
+                .ok()
+                .filter(|topic| !topic.is_empty()),
+            mqtt_ambient_field: var("MQTT_AMBIENT_FIELD")
+                .unwrap_or_else(|_| "temperature".to_string()),
+            mqtt_ambient_max_age: Duration::from_secs(env_or(
+                &var,
+                "MQTT_AMBIENT_MAX_AGE_SECS",
+                300,
+            )?),
+            max_snapshot_bytes: env_or(&var, "MAX_SNAPSHOT_BYTES", 0)?,
+            max_consecutive_failures: env_or(&var, "MAX_CONSECUTIVE_FAILURES", 10)?,
+        };
+        if config.align_to_wall_clock && config.collection_jitter_percent > 0.0 {
+            anyhow::bail!("ALIGN_TO_WALL_CLOCK and COLLECTION_JITTER_PERCENT can't be combined");
+        }
+        Ok(config)
+    }
+
+    // The settings in effect, keyed by environment variable and written the
+    // way they would be set. The hook command, custom command arguments and
+    // control token can carry credentials, so they are masked.
+    pub fn effective(&self) -> EffectiveConfig {
+        let join = |items: Vec<String>| items.join(",");
+        let seconds = |duration: Duration| duration.as_secs();
+        let millis = |duration: Duration| duration.as_millis() as u64;
+        let display = |path: &Option<PathBuf>| path.as_ref().map(|p| p.display().to_string());
+        EffectiveConfig {
+            port: self.port,
+            port_fallback: self.port_fallback.to_string(),
+            base_path: self.base_path.clone(),
+            bind_interface: self.bind_interface.clone(),
+            history_size: self.history_size,
+            expensive_interval_secs: seconds(self.expensive_interval),
+            max_heavy_collectors: self.max_heavy_collectors,
+            cpu_sample_window_ms: millis(self.cpu_sample_window),
+            consistent_reads: self.consistent_reads,
+            profile_refresh: self.profile_refresh,
+            core_usage: match self.core_usage_mode {
+                CoreUsageMode::Full => "full",
+                CoreUsageMode::Summary => "summary",
+                CoreUsageMode::Both => "both",
+            },
+            collect_interrupts: self.collect_interrupts,
+            collect_usb: self.collect_usb,
+            collect_cpu: self.collect_cpu,
+            collect_memory: self.collect_memory,
+            collect_storage: self.collect_storage,
+            collect_network: self.collect_network,
+            collect_temperature: self.collect_temperature,
+            interrupts_top_n: self.interrupts_top_n,
+            labels: join(
+                self.labels
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect(),
+            ),
+            source_name: self.source_name.clone(),
+            metrics_prefix: self.metrics_prefix.clone(),
+            instance_label: self.instance_label.clone(),
+            cache_static_info: self.cache_static_info,
+            redact_fields: join(
+                self.redact
+                    .iter()
+                    .map(|field| {
+                        match field {
+                            RedactedField::Hostname => "hostname",
+                            RedactedField::LocalIps => "local_ips",
+                            RedactedField::SerialNumber => "serial_number",
+                            RedactedField::CurrentUser => "current_user",
+                        }
+                        .to_string()
+                    })
+                    .collect(),
+            ),
+            collect_cooling_devices: self.collect_cooling_devices,
+            thermal_critical_temp: self.thermal_critical_temp,
+            custom_paths: join(
+                self.custom_paths
+                    .iter()
+                    .map(|custom| {
+                        format!(
+                            "{}={}:{}",
+                            custom.name,
+                            custom.kind.as_str(),
+                            custom.path.display()
+                        )
+                    })
+                    .collect(),
+            ),
+            custom_commands: self
+                .custom_commands
+                .iter()
+                .map(|custom| format!("{}={}:{}", custom.name, custom.kind.as_str(), REDACTED))
+                .collect::<Vec<_>>()
+                .join(";"),
+            custom_command_timeout_ms: millis(self.custom_command_timeout),
+            custom_command_concurrency: self.custom_command_concurrency,
+            network_namespaces: join(
+                self.network_namespaces
+                    .iter()
+                    .map(|ns| ns.name.clone())
+                    .collect(),
+            ),
+            disk_alerts: join(
+                self.disk_alerts
+                    .iter()
+                    .map(|rule| format!("{}={}", rule.mount_point, rule.max_percent))
+                    .collect(),
+            ),
+            missed_tick_behavior: match self.missed_tick_behavior {
+                MissedTickBehavior::Burst => "burst",
+                MissedTickBehavior::Delay => "delay",
+                MissedTickBehavior::Skip => "skip",
+            },
+            load_backoff: self.load_backoff,
+            load_backoff_threshold: self.load_backoff_threshold,
+            collection_jitter_percent: self.collection_jitter_percent,
+            align_to_wall_clock: self.align_to_wall_clock,
+            health_max_cpu_temp: self.health_thresholds.max_cpu_temp,
+            health_max_disk_percent: self.health_thresholds.max_disk_percent,
+            health_fail_on_read_only_root: self.health_thresholds.fail_on_read_only_root,
+            health_max_journal_growth_mib_per_hour: self
+                .health_thresholds
+                .max_journal_growth_mib_per_hour,
+            on_snapshot_command: self.on_snapshot_command.as_ref().map(|_| REDACTED),
+            on_snapshot_timeout_secs: seconds(self.on_snapshot_timeout),
+            on_snapshot_min_interval_secs: seconds(self.on_snapshot_min_interval),
+            on_snapshot_stdin: self.on_snapshot_stdin,
+            signal_dump_dir: display(&self.signal_dump_dir),
+            broadcast_only_changes: self.broadcast_only_changes,
+            broadcast_tolerance: self.broadcast_tolerance,
+            broadcast_keepalive_secs: seconds(self.broadcast_keepalive),
+            expose_config: self.expose_config,
+            control_token: self.control_token.as_ref().map(|_| REDACTED),
+            mqtt_broker: self.mqtt_broker.clone(),
+            mqtt_ambient_topic: self.mqtt_ambient_topic.clone(),
+            mqtt_ambient_field: self.mqtt_ambient_field.clone(),
+            mqtt_ambient_max_age_secs: seconds(self.mqtt_ambient_max_age),
+            max_snapshot_bytes: self.max_snapshot_bytes,
+            max_consecutive_failures: self.max_consecutive_failures,
+            static_dir: display(&self.static_dir),
+            static_reload_debounce_ms: millis(self.static_reload_debounce),
+        }
+    }
+}
+
+// Response for /api/config: one field per environment variable
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct EffectiveConfig {
+    port: u16,
+    port_fallback: String,
+    base_path: String,
+    bind_interface: Option<String>,
+    history_size: usize,
+    expensive_interval_secs: u64,
+    max_heavy_collectors: usize,
+    cpu_sample_window_ms: u64,
+    consistent_reads: bool,
+    profile_refresh: bool,
+    core_usage: &'static str,
+    collect_interrupts: bool,
+    collect_usb: bool,
+    collect_cpu: bool,
+    collect_memory: bool,
+    collect_storage: bool,
+    collect_network: bool,
+    collect_temperature: bool,
+    interrupts_top_n: usize,
+    labels: String,
+    source_name: String,
+    metrics_prefix: String,
+    instance_label: Option<String>,
+    cache_static_info: bool,
+    redact_fields: String,
+    collect_cooling_devices: bool,
+    thermal_critical_temp: f32,
+    custom_paths: String,
+    custom_commands: String,
+    custom_command_timeout_ms: u64,
+    custom_command_concurrency: usize,
+    network_namespaces: String,
+    disk_alerts: String,
+    missed_tick_behavior: &'static str,
+    load_backoff: bool,
+    load_backoff_threshold: f64,
+    collection_jitter_percent: f64,
+    align_to_wall_clock: bool,
+    health_max_cpu_temp: f32,
+    health_max_disk_percent: f32,
+    health_fail_on_read_only_root: bool,
+    health_max_journal_growth_mib_per_hour: f64,
+    on_snapshot_command: Option<&'static str>,
+    on_snapshot_timeout_secs: u64,
+    on_snapshot_min_interval_secs: u64,
+    on_snapshot_stdin: bool,
+    signal_dump_dir: Option<String>,
+    broadcast_only_changes: bool,
+    broadcast_tolerance: f64,
+    broadcast_keepalive_secs: u64,
+    expose_config: bool,
+    control_token: Option<&'static str>,
+    mqtt_broker: String,
+    mqtt_ambient_topic: Option<String>,
+    mqtt_ambient_field: String,
+    mqtt_ambient_max_age_secs: u64,
+    max_snapshot_bytes: usize,
+    max_consecutive_failures: u32,
+    static_dir: Option<String>,
+    static_reload_debounce_ms: u64,
+}
+
+// What to do when the configured port is already in use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortFallback {
+    // Fail to start, so a deployment never ends up on an unexpected port
+    Strict,
+    // Try up to this many following ports
+    Next(u16),
+    // Let the kernel pick any free port
+    Ephemeral,
+}
+
+impl FromStr for PortFallback {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "ephemeral" => Ok(Self::Ephemeral),
+            _ => match s.parse::<u16>() {
+                Ok(0) => Ok(Self::Strict),
+                Ok(count) => Ok(Self::Next(count)),
+                Err(_) => anyhow::bail!(
+                    "Invalid PORT_FALLBACK {:?}: expected strict, ephemeral or a number of ports",
+                    s
+                ),
+            },
+        }
+    }
+}
+
+impl Display for PortFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strict => f.write_str("strict"),
+            Self::Next(count) => write!(f, "{}", count),
+            Self::Ephemeral => f.write_str("ephemeral"),
+        }
+    }
+}
+
+// How per-core CPU usage is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreUsageMode {
+    Full,
+    Summary,
+    Both,
+}
+
+impl FromStr for CoreUsageMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "summary" => Ok(Self::Summary),
+            "both" => Ok(Self::Both),
+            _ => anyhow::bail!("Invalid CORE_USAGE {:?}: expected full, summary or both", s),
+        }
+    }
+}
+
+// Parse `key=value,key=value` labels. Keys follow Prometheus label naming and
+// values avoid characters that need escaping in Prometheus/Influx/MQTT output.
+fn parse_labels(spec: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut labels = BTreeMap::new();
+    for pair in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid label {:?}: expected key=value", pair))?;
+        let (key, value) = (key.trim(), value.trim());
+
+        let valid_key = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !key.starts_with("__");
+        if !valid_key {
+            anyhow::bail!(
+                "Invalid label key {:?}: use letters, digits and underscores, not starting with a digit or __",
+                key
+            );
+        }
+        // /metrics labels every series with SOURCE_NAME as `source` and
+        // INSTANCE_LABEL as `instance`
+        if key == "source" {
+            anyhow::bail!("Invalid label key \"source\": set SOURCE_NAME instead");
+        }
+        if key == "instance" {
+            anyhow::bail!("Invalid label key \"instance\": set INSTANCE_LABEL instead");
+        }
+        let valid_value = !value.is_empty()
+            && !value.chars().any(|c| {
+                c.is_whitespace() || c.is_control() || matches!(c, ',' | '=' | '"' | '\\')
+            });
+        if !valid_value {
+            anyhow::bail!(
+                "Invalid value for label {:?}: must be non-empty without whitespace, quotes, backslashes, ',' or '='",
+                key
+            );
+        }
+
+        labels.insert(key.to_string(), value.to_string());
+    }
+    Ok(labels)
+}
+
+// Check METRICS_PREFIX is a valid start of a Prometheus metric name
+fn parse_metrics_prefix(prefix: &str) -> anyhow::Result<String> {
+    let prefix = prefix.trim().trim_end_matches('_');
+    let valid = prefix.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if !valid {
+        anyhow::bail!(
+            "Invalid METRICS_PREFIX={:?}: use letters, digits, underscores and colons, not starting with a digit",
+            prefix
+        );
+    }
+    Ok(prefix.to_string())
+}
+
+// Parse an environment variable, falling back to a default when it is unset
+fn env_or<T>(
+    var: impl Fn(&str) -> Result<String, env::VarError>,
+    name: &str,
+    default: T,
+) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid {}={:?}: {}", name, value, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    // Defaults plus the given settings, whatever the test process's environment
+    pub fn from_pairs(vars: &[(&str, &str)]) -> Self {
+        Self::from_vars(|name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+                .ok_or(env::VarError::NotPresent)
+        })
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_prefix_must_be_a_metric_name() {
+        assert_eq!(parse_metrics_prefix("pi").unwrap(), "pi");
+        assert_eq!(parse_metrics_prefix("rack_3_").unwrap(), "rack_3");
+        assert!(parse_metrics_prefix("3rack").is_err());
+        assert!(parse_metrics_prefix("my-pi").is_err());
+    }
+
+    #[test]
+    fn exporter_names_default_to_lop_and_the_hostname() {
+        let config = Config::from_pairs(&[]);
+        assert_eq!(config.metrics_prefix, "lop");
+        assert_eq!(config.instance_label, None);
+
+        let config = Config::from_pairs(&[("INSTANCE_LABEL", " attic-pi ")]);
+        assert_eq!(config.instance_label.as_deref(), Some("attic-pi"));
+        assert!(parse_labels("instance=attic-pi").is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! Snapshot exports other than JSON: Prometheus text and CSV
+
+use crate::snapshot::{NetworkInterface, SystemSnapshot};
+
+// One metric family per gauge or counter, named `<METRICS_PREFIX>_...`.
+// Every series is labelled with the snapshot's source and `instance`, then
+// LABELS, after the series' own labels.
+pub fn prometheus_text(snapshot: &SystemSnapshot, prefix: &str, instance: &str) -> String {
+    use std::fmt::Write;
+
+    let escape = |value: &str| {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    };
+    let label_set = |labels: &[(&str, &str)]| {
+        let pairs: Vec<String> = labels
+            .iter()
+            .copied()
+            .chain([("source", snapshot.source.as_str()), ("instance", instance)])
+            .chain(
+                snapshot
+                    .labels
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str())),
+            )
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect();
+        if pairs.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", pairs.join(","))
+        }
+    };
+
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: &[(Vec<(&str, &str)>, f64)]| {
+        if samples.is_empty() {
+            return;
+        }
+        let name = format!("{}_{}", prefix, name);
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, label_set(labels), value);
+        }
+    };
+    let gauge = |value: f64| vec![(Vec::new(), value)];
+
+    family(
+        "cpu_usage_percent",
+        "gauge",
+        "CPU usage across all cores",
+        &gauge(snapshot.cpu_usage.into()),
+    );
+    let cores: Vec<String> = (0..snapshot.core_usage.len())
+        .map(|core| core.to_string())
+        .collect();
+    let core_usage: Vec<_> = cores
+        .iter()
+        .zip(&snapshot.core_usage)
+        .map(|(core, &usage)| (vec![("core", core.as_str())], f64::from(usage)))
+        .collect();
+    family(
+        "cpu_core_usage_percent",
+        "gauge",
+        "CPU usage per core",
+        &core_usage,
+    );
+    family(
+        "cpu_temp_celsius",
+        "gauge",
+        "CPU temperature",
+        &gauge(snapshot.cpu_temp.into()),
+    );
+    family(
+        "memory_used_bytes",
+        "gauge",
+        "RAM in use",
+        &gauge(snapshot.memory_used as f64),
+    );
+    family(
+        "memory_total_bytes",
+        "gauge",
+        "Total RAM",
+        &gauge(snapshot.memory_total as f64),
+    );
+    family(
+        "swap_used_bytes",
+        "gauge",
+        "Swap in use",
+        &gauge(snapshot.swap_used as f64),
+    );
+    family(
+        "swap_total_bytes",
+        "gauge",
+        "Total swap",
+        &gauge(snapshot.swap_total as f64),
+    );
+    family(
+        "disk_used_bytes",
+        "gauge",
+        "Space used on the root filesystem",
+        &gauge(snapshot.disk_used as f64),
+    );
+    family(
+        "disk_total_bytes",
+        "gauge",
+        "Size of the root filesystem",
+        &gauge(snapshot.disk_total as f64),
+    );
+    family(
+        "load1",
+        "gauge",
+        "1-minute load average",
+        &gauge(snapshot.load_avg_1m),
+    );
+    family(
+        "load5",
+        "gauge",
+        "5-minute load average",
+        &gauge(snapshot.load_avg_5m),
+    );
+    family(
+        "load15",
+        "gauge",
+        "15-minute load average",
+        &gauge(snapshot.load_avg_15m),
+    );
+    family(
+        "uptime_seconds",
+        "gauge",
+        "Time since boot",
+        &gauge(snapshot.uptime as f64),
+    );
+
+    // Namespaced interfaces get a namespace label so names don't collide
+    fn interface_labels(interface: &NetworkInterface) -> Vec<(&str, &str)> {
+        let mut labels = vec![("interface", interface.name.as_str())];
+        if let Some(namespace) = &interface.namespace {
+            labels.push(("namespace", namespace.as_str()));
+        }
+        labels
+    }
+    let rx: Vec<_> = snapshot
+        .network_interfaces
+        .iter()
+        .map(|interface| (interface_labels(interface), interface.rx_bytes as f64))
+        .collect();
+    let tx: Vec<_> = snapshot
+        .network_interfaces
+        .iter()
+        .map(|interface| (interface_labels(interface), interface.tx_bytes as f64))
+        .collect();
+    family(
+        "network_rx_bytes_total",
+        "counter",
+        "Bytes received per interface",
+        &rx,
+    );
+    family(
+        "network_tx_bytes_total",
+        "counter",
+        "Bytes transmitted per interface",
+        &tx,
+    );
+    out
+}
+
+// Columns of the history CSV download
+pub const HISTORY_CSV_HEADER: &str = "timestamp,timestamp_rfc3339,sequence,cpu_usage,cpu_temp,\
+memory_used,memory_total,memory_percent,swap_used,swap_total,disk_used,disk_total,disk_percent,\
+network_rx,network_tx,load_avg_1m,load_avg_5m,load_avg_15m\n";
+
+// The fields of one CSV row, copied out of a snapshot so the history lock
+// isn't held while rows are formatted and sent
+pub struct HistoryCsvRow {
+    timestamp: u64,
+    timestamp_rfc3339: String,
+    sequence: u64,
+    cpu_usage: f32,
+    cpu_temp: f32,
+    memory_used: u64,
+    memory_total: u64,
+    memory_percent: f32,
+    swap_used: u64,
+    swap_total: u64,
+    disk_used: u64,
+    disk_total: u64,
+    disk_percent: f32,
+    network_rx: u64,
+    network_tx: u64,
+    load_avg_1m: f64,
+    load_avg_5m: f64,
+    load_avg_15m: f64,
+}
+
+impl HistoryCsvRow {
+    pub fn new(snapshot: &SystemSnapshot) -> Self {
+        Self {
+            timestamp: snapshot.timestamp,
+            timestamp_rfc3339: snapshot.timestamp_rfc3339.clone(),
+            sequence: snapshot.sequence,
+            cpu_usage: snapshot.cpu_usage,
+            cpu_temp: snapshot.cpu_temp,
+            memory_used: snapshot.memory_used,
+            memory_total: snapshot.memory_total,
+            memory_percent: snapshot.memory_percent,
+            swap_used: snapshot.swap_used,
+            swap_total: snapshot.swap_total,
+            disk_used: snapshot.disk_used,
+            disk_total: snapshot.disk_total,
+            disk_percent: snapshot.disk_percent,
+            network_rx: snapshot.network_rx,
+            network_tx: snapshot.network_tx,
+            load_avg_1m: snapshot.load_avg_1m,
+            load_avg_5m: snapshot.load_avg_5m,
+            load_avg_15m: snapshot.load_avg_15m,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            self.timestamp,
+            self.timestamp_rfc3339,
+            self.sequence,
+            self.cpu_usage,
+            self.cpu_temp,
+            self.memory_used,
+            self.memory_total,
+            self.memory_percent,
+            self.swap_used,
+            self.swap_total,
+            self.disk_used,
+            self.disk_total,
+            self.disk_percent,
+            self.network_rx,
+            self.network_tx,
+            self.load_avg_1m,
+            self.load_avg_5m,
+            self.load_avg_15m,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn prometheus_series_carry_source_instance_and_prefix() {
+        let snapshot = SystemSnapshot {
+            source: "attic".to_string(),
+            cpu_usage: 12.345,
+            core_usage: vec![10.1, 0.3, 99.9, 33.3],
+            labels: BTreeMap::from([("role".to_string(), "sensor".to_string())]),
+            ..Default::default()
+        };
+        let text = prometheus_text(&snapshot, "node", "pi-4");
+        assert!(text.contains(
+            "node_cpu_usage_percent{source=\"attic\",instance=\"pi-4\",role=\"sensor\"} "
+        ));
+        assert!(text.contains(
+            "node_cpu_core_usage_percent{core=\"2\",source=\"attic\",instance=\"pi-4\",role=\"sensor\"} "
+        ));
+        assert!(text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .all(|line| line.starts_with("node_") && line.contains("instance=\"pi-4\"")));
+    }
+}
@@ -1,339 +1,524 @@
-use axum::{
-    extract::State,
-    response::{Html, Json},
-    routing::{get, Router},
-    serve,
-};
-use serde::{Deserialize, Serialize};
+mod collector;
+mod config;
+mod export;
+mod snapshot;
+mod web;
+
+use axum::serve;
+use serde::Serialize;
 use std::{
+    collections::VecDeque,
     env, fs,
-    net::SocketAddr,
-    process::Command,
-    sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    net::{IpAddr, Ipv4Addr},
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use sysinfo::{Disks, Networks, System};
-use tokio::{net::TcpListener, time::interval};
-use tower_http::{cors::CorsLayer, services::ServeDir};
-use tracing::info;
-
-// System metrics snapshot
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct SystemSnapshot {
-    timestamp: u64,
-    cpu_usage: f32,
-    cpu_temp: f32,
-    memory_total: u64,
-    memory_used: u64,
-    memory_percent: f32,
-    disk_total: u64,
-    disk_used: u64,
-    disk_percent: f32,
-    network_rx: u64,
-    network_tx: u64,
-    // System information
-    hostname: String,
-    os_name: String,
-    kernel_version: String,
-    uptime: u64, // seconds
-    load_avg_1m: f64,
-    load_avg_5m: f64,
-    load_avg_15m: f64,
-    current_user: String,
-    local_ips: Vec<String>,
-    pi_model: Option<String>,
-    is_raspberry_pi: bool,
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+    time::interval,
+};
+use tracing::{error, info, warn};
+
+use collector::{collect_expensive_metrics, spawn_custom_commands, Capabilities, SystemCollector};
+use config::Config;
+use snapshot::SystemSnapshot;
+use web::{bind_listener, interface_address, watch_dashboard, AppState};
+
+// Support bundle written by `--diagnostics <path>`
+#[derive(Debug, Serialize)]
+struct DiagnosticsBundle {
+    version: &'static str,
+    generated_at: u64, // milliseconds since epoch
+    capabilities: Capabilities,
+    warnings: Vec<String>,
+    snapshots: Vec<SystemSnapshot>,
 }
 
-#[derive(Clone)]
-struct AppState {
-    latest_snapshot: Arc<tokio::sync::RwLock<SystemSnapshot>>,
-}
+// Number of snapshots captured in a diagnostics bundle, one second apart
+const DIAGNOSTICS_SNAPSHOTS: usize = 3;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize logging
     tracing_subscriber::fmt::init();
 
+    let config = Arc::new(Config::from_env()?);
+
+    // One-shot mode: write a support bundle and exit
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(flag) = args.iter().position(|arg| arg == "--diagnostics") {
+        let path = args
+            .get(flag + 1)
+            .ok_or_else(|| anyhow::anyhow!("--diagnostics requires an output path"))?;
+        return write_diagnostics(&config, PathBuf::from(path)).await;
+    }
+
     info!("🥧 Life of Pi - Starting Raspberry Pi Monitor");
 
     // Create initial state
+    let capabilities = Arc::new(Capabilities::probe());
+    let mut collector = SystemCollector::new(&config)?;
+    if let Some(topic) = &config.mqtt_ambient_topic {
+        #[cfg(feature = "mqtt")]
+        {
+            collector.ambient = Some(collector::subscribe_ambient_temperature(&config, topic)?);
+        }
+        #[cfg(not(feature = "mqtt"))]
+        anyhow::bail!(
+            "MQTT_AMBIENT_TOPIC={} needs a build with the mqtt feature",
+            topic
+        );
+    }
+    if !config.custom_commands.is_empty() {
+        collector.custom_command_values = Some(spawn_custom_commands(&config, COLLECTION_PERIOD));
+    }
+    let heavy_permits = collector.heavy_permits.clone();
+    let (expensive_tx, expensive_rx) =
+        watch::channel(collect_expensive_metrics(&heavy_permits).await);
+    let initial_snapshot = collector.collect(&expensive_rx.borrow())?;
+    let (snapshot_tx, snapshot_rx) = watch::channel(initial_snapshot);
     let app_state = AppState {
-        latest_snapshot: Arc::new(tokio::sync::RwLock::new(get_system_snapshot())),
+        config: config.clone(),
+        capabilities,
+        latest_snapshot: snapshot_rx,
+        history: Arc::new(tokio::sync::RwLock::new(VecDeque::with_capacity(
+            config.history_size,
+        ))),
+        collection_paused: Arc::new(AtomicBool::new(false)),
+        collection_failures: Arc::new(AtomicU32::new(0)),
+        dashboard_html: match &config.static_dir {
+            Some(dir) => Some(watch_dashboard(dir.clone(), config.static_reload_debounce).await),
+            None => None,
+        },
     };
 
-    // Start background metrics collection
-    let state_clone = app_state.clone();
+    // Start background refresh of expensive metrics
+    let expensive_interval = config.expensive_interval;
     tokio::spawn(async move {
-        let mut interval = interval(Duration::from_secs(2));
+        let mut interval = interval(expensive_interval);
+        // The first tick completes immediately and we already have fresh values
+        interval.tick().await;
         loop {
             interval.tick().await;
-            let snapshot = get_system_snapshot();
-            *state_clone.latest_snapshot.write().await = snapshot;
+            expensive_tx.send_replace(collect_expensive_metrics(&heavy_permits).await);
         }
     });
 
-    // Create router
-    let app = Router::new()
-        .route("/", get(dashboard))
-        .route("/api/metrics", get(get_metrics))
-        .nest_service("/static", ServeDir::new("static"))
-        .layer(CorsLayer::permissive())
-        .with_state(app_state);
+    // Run the snapshot hook in its own task, fed by the collection loop on
+    // every collection (not only the ones broadcast to streaming clients). It
+    // reads the latest snapshot when it is ready for another run, so a slow
+    // hook skips snapshots instead of queueing them up behind the collection.
+    let hook_tx = config.on_snapshot_command.clone().map(|command| {
+        info!("Running `{}` after each snapshot", command);
+        let config = config.clone();
+        let (hook_tx, mut snapshots) = watch::channel(SystemSnapshot::default());
+        tokio::spawn(async move {
+            while snapshots.changed().await.is_ok() {
+                let snapshot = snapshots.borrow_and_update().clone();
+                run_snapshot_hook(&command, &config, &snapshot).await;
+                tokio::time::sleep(config.on_snapshot_min_interval).await;
+            }
+        });
+        hook_tx
+    });
+
+    // Start background metrics collection. This task is the only owner of
+    // the collector; everything else reads the published snapshots.
+    // SIGUSR1 collects a snapshot right away and dumps it
+    let mut dump_snapshot_signal = signal(SignalKind::user_defined1())?;
+    let state_clone = app_state.clone();
+    tokio::spawn(async move {
+        let base_period = COLLECTION_PERIOD;
+        let align = state_clone.config.align_to_wall_clock;
+        let mut interval = interval(base_period);
+        interval.set_missed_tick_behavior(state_clone.config.missed_tick_behavior);
+        if align {
+            interval.reset_after(delay_to_boundary(base_period));
+        }
+        let mut backoff = 1;
+        let mut previous_timestamp = snapshot_tx.borrow().timestamp;
+        // A pause stretches the next interval, which isn't falling behind
+        let mut was_paused = false;
+        let mut last_broadcast = Instant::now();
+        // What streaming clients last received. Changes are measured against
+        // it rather than the previous collection, so slow drift still adds up.
+        let mut broadcast_baseline = snapshot_tx.borrow().clone();
+        loop {
+            let dump_requested = tokio::select! {
+                _ = interval.tick() => false,
+                _ = dump_snapshot_signal.recv() => true,
+            };
+            // Clients keep the last snapshot while paused; a dump still collects
+            if state_clone.collection_paused.load(Ordering::Relaxed) && !dump_requested {
+                was_paused = true;
+                continue;
+            }
+            // The monotonic ticker drifts from the wall clock (NTP slews it),
+            // so aligned mode recomputes every deadline from the wall clock
+            let jitter = state_clone.config.collection_jitter_percent;
+            if align {
+                interval.reset_after(delay_to_boundary(interval.period()));
+            } else if jitter > 0.0 {
+                interval.reset_after(jittered(interval.period(), jitter));
+            }
+            let expensive_metrics = expensive_rx.borrow().clone();
+            // Giving up drops the sender, which ends the event streams
+            let attempt = attempt_collection(
+                &state_clone.collection_failures,
+                state_clone.config.max_consecutive_failures,
+                || tokio::task::block_in_place(|| collector.collect(&expensive_metrics)),
+            );
+            let mut snapshot = match attempt {
+                ControlFlow::Continue(Some(snapshot)) => snapshot,
+                ControlFlow::Continue(None) => continue,
+                ControlFlow::Break(()) => break,
+            };
+            let actual_interval_ms = snapshot.timestamp.saturating_sub(previous_timestamp);
+            let allowed = interval
+                .period()
+                .mul_f64(FALLING_BEHIND_FACTOR + jitter / 100.0);
+            snapshot.actual_interval_ms = Some(actual_interval_ms);
+            snapshot.falling_behind =
+                !was_paused && actual_interval_ms as u128 > allowed.as_millis();
+            was_paused = false;
+            previous_timestamp = snapshot.timestamp;
+            snapshot.sequence = snapshot_tx.borrow().sequence + 1;
+
+            if state_clone.config.load_backoff {
+                let factor = load_backoff_factor(
+                    snapshot.load_avg_1m,
+                    state_clone.config.load_backoff_threshold,
+                );
+                if factor != backoff {
+                    let period = base_period * factor;
+                    if factor > 1 {
+                        info!(
+                            "Load {:.2} is high, collecting every {:?}",
+                            snapshot.load_avg_1m, period
+                        );
+                    } else {
+                        info!(
+                            "Load {:.2} is back to normal, collecting every {:?}",
+                            snapshot.load_avg_1m, period
+                        );
+                    }
+                    let first = if align {
+                        delay_to_boundary(period)
+                    } else {
+                        period
+                    };
+                    interval =
+                        tokio::time::interval_at(tokio::time::Instant::now() + first, period);
+                    interval.set_missed_tick_behavior(state_clone.config.missed_tick_behavior);
+                    backoff = factor;
+                }
+            }
+            {
+                let mut history = state_clone.history.write().await;
+                if history.len() >= state_clone.config.history_size {
+                    history.pop_front();
+                }
+                if state_clone.config.history_size > 0 {
+                    history.push_back(snapshot.clone());
+                }
+            }
+            if dump_requested {
+                let dir = state_clone.config.signal_dump_dir.as_deref();
+                dump_json("snapshot", snapshot.timestamp, &snapshot, dir).await;
+            }
+            if let Some(hook_tx) = &hook_tx {
+                hook_tx.send_replace(snapshot.clone());
+            }
+            // REST handlers always read the newest value; streaming clients
+            // are only woken up for material changes or the keepalive
+            let config = &state_clone.config;
+            let notify = !config.broadcast_only_changes
+                || last_broadcast.elapsed() >= config.broadcast_keepalive
+                || !snapshot.materially_equal(&broadcast_baseline, config.broadcast_tolerance);
+            if notify {
+                last_broadcast = Instant::now();
+                if config.broadcast_only_changes {
+                    broadcast_baseline = snapshot.clone();
+                }
+            }
+            snapshot_tx.send_if_modified(|latest| {
+                *latest = snapshot;
+                notify
+            });
+        }
+    });
+
+    // SIGUSR2 dumps the in-memory history
+    let mut dump_history_signal = signal(SignalKind::user_defined2())?;
+    let state_clone = app_state.clone();
+    tokio::spawn(async move {
+        while dump_history_signal.recv().await.is_some() {
+            let history: Vec<SystemSnapshot> =
+                state_clone.history.read().await.iter().cloned().collect();
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let dir = state_clone.config.signal_dump_dir.as_deref();
+            dump_json("history", now, &history, dir).await;
+        }
+    });
+
+    let app = web::router(&config, app_state);
 
     // Start server
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()?;
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let ip = match &config.bind_interface {
+        Some(name) => interface_address(name)?,
+        None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+    };
+    let listener = bind_listener(ip, config.port, config.port_fallback).await?;
+    let addr = listener.local_addr()?;
+    let port = addr.port();
 
     info!("Starting server on http://{}", addr);
-    info!("Dashboard: http://localhost:{}", port);
-    info!("API: http://localhost:{}/api/metrics", port);
+    let base = &config.base_path;
+    info!("Dashboard: http://localhost:{}{}/", port, base);
+    info!("API: http://localhost:{}{}/api/metrics", port, base);
+    info!("Health: http://localhost:{}{}/api/health", port, base);
+    info!("Events: http://localhost:{}{}/api/events", port, base);
 
-    let listener = TcpListener::bind(addr).await?;
     serve(listener, app).await?;
 
     Ok(())
 }
 
-// Get current system metrics
-fn get_system_snapshot() -> SystemSnapshot {
-    let mut sys = System::new_all();
-    sys.refresh_all();
-
-    // CPU usage (global usage)
-    let cpu_usage = sys.global_cpu_usage();
+// Capture a few snapshots plus system facts into a single JSON file
+async fn write_diagnostics(config: &Config, path: PathBuf) -> anyhow::Result<()> {
+    info!("Collecting diagnostics bundle");
 
-    // Memory
-    let memory_total = sys.total_memory();
-    let memory_used = sys.used_memory();
-    let memory_percent = if memory_total > 0 {
-        (memory_used as f32 / memory_total as f32) * 100.0
-    } else {
-        0.0
-    };
-
-    // Disk (use root filesystem)
-    let mut disk_total = 0;
-    let mut disk_used = 0;
-    let disks = Disks::new_with_refreshed_list();
-    for disk in &disks {
-        if disk.mount_point().to_str().unwrap_or("") == "/" {
-            disk_total = disk.total_space();
-            disk_used = disk_total - disk.available_space();
-            break;
-        }
+    let capabilities = Capabilities::probe();
+    let mut collector = SystemCollector::new(config)?;
+    if !config.custom_commands.is_empty() {
+        let values = spawn_custom_commands(config, COLLECTION_PERIOD);
+        collector.custom_command_values = Some(values);
     }
-    let disk_percent = if disk_total > 0 {
-        (disk_used as f32 / disk_total as f32) * 100.0
-    } else {
-        0.0
-    };
-
-    // Network (sum all interfaces)
-    let mut network_rx = 0;
-    let mut network_tx = 0;
-    let networks = Networks::new_with_refreshed_list();
-    for (_name, network) in &networks {
-        network_rx += network.total_received();
-        network_tx += network.total_transmitted();
+    let expensive_metrics = collect_expensive_metrics(&collector.heavy_permits).await;
+    let mut snapshots = Vec::with_capacity(DIAGNOSTICS_SNAPSHOTS);
+    for i in 0..DIAGNOSTICS_SNAPSHOTS {
+        if i > 0 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        snapshots.push(collector.collect(&expensive_metrics)?);
     }
 
-    // CPU temperature (Raspberry Pi specific)
-    let cpu_temp = read_cpu_temperature().unwrap_or(0.0);
-
-    // System information
-    let hostname = System::host_name().unwrap_or_else(|| "unknown".to_string());
-    let os_name = System::long_os_version().unwrap_or_else(|| "Unknown OS".to_string());
-    let kernel_version = System::kernel_version().unwrap_or_else(|| "Unknown".to_string());
-    let uptime = System::uptime();
-    let load_avg = System::load_average();
-    let current_user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
-    let local_ips = get_local_ip_addresses();
-    let pi_model = get_pi_model();
-    let is_raspberry_pi = pi_model.is_some();
-
-    SystemSnapshot {
-        timestamp: SystemTime::now()
+    let bundle = DiagnosticsBundle {
+        version: env!("CARGO_PKG_VERSION"),
+        generated_at: SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_millis() as u64,
-        cpu_usage,
-        cpu_temp,
-        memory_total,
-        memory_used,
-        memory_percent,
-        disk_total,
-        disk_used,
-        disk_percent,
-        network_rx,
-        network_tx,
-        hostname,
-        os_name,
-        kernel_version,
-        uptime,
-        load_avg_1m: load_avg.one,
-        load_avg_5m: load_avg.five,
-        load_avg_15m: load_avg.fifteen,
-        current_user,
-        local_ips,
-        pi_model,
-        is_raspberry_pi,
-    }
+        warnings: capabilities.warnings(),
+        capabilities,
+        snapshots,
+    };
+    let file = fs::File::create(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), &bundle)?;
+
+    info!("Diagnostics written to {}", path.display());
+    Ok(())
 }
 
-// Get local IP addresses
-fn get_local_ip_addresses() -> Vec<String> {
-    use std::net::IpAddr;
-
-    let mut ips = Vec::new();
-
-    if let Ok(output) = Command::new("hostname").arg("-I").output() {
-        if output.status.success() {
-            let ip_string = String::from_utf8_lossy(&output.stdout);
-            for ip in ip_string.split_whitespace() {
-                if let Ok(parsed_ip) = ip.parse::<IpAddr>() {
-                    match parsed_ip {
-                        IpAddr::V4(ipv4) => {
-                            if !ipv4.is_loopback() && !ipv4.is_link_local() {
-                                ips.push(ip.to_string());
-                            }
-                        }
-                        IpAddr::V6(ipv6) => {
-                            if !ipv6.is_loopback() && !ipv6.is_unspecified() {
-                                ips.push(ip.to_string());
-                            }
-                        }
-                    }
-                }
+// Time until the wall clock next reaches a whole multiple of `period`
+fn delay_to_boundary(period: Duration) -> Duration {
+    let period_ms = period.as_millis().max(1);
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    Duration::from_millis((period_ms - now_ms % period_ms) as u64)
+}
+
+// Run one collection, keeping `failures` at the number of failed attempts in
+// a row (reported by /api/health). A failure yields no snapshot, so clients
+// keep the last good one; only a run of `limit` failures breaks, and 0 never
+// does.
+fn attempt_collection(
+    failures: &AtomicU32,
+    limit: u32,
+    collect: impl FnOnce() -> anyhow::Result<SystemSnapshot>,
+) -> ControlFlow<(), Option<SystemSnapshot>> {
+    match collect() {
+        Ok(snapshot) => {
+            let failed = failures.swap(0, Ordering::Relaxed);
+            if failed > 0 {
+                info!("Collection recovered after {} failures", failed);
             }
+            ControlFlow::Continue(Some(snapshot))
         }
-    }
-
-    // Fallback: try to get interface info from /proc/net/route and ifconfig
-    if ips.is_empty() {
-        if let Ok(output) = Command::new("ip")
-            .args(["route", "get", "8.8.8.8"])
-            .output()
-        {
-            if output.status.success() {
-                let route_info = String::from_utf8_lossy(&output.stdout);
-                // Parse "src <IP>" from the output
-                for line in route_info.lines() {
-                    if let Some(src_idx) = line.find("src ") {
-                        let ip_part = &line[src_idx + 4..];
-                        if let Some(ip_end) = ip_part.find(' ') {
-                            let ip = &ip_part[..ip_end];
-                            if let Ok(_) = ip.parse::<IpAddr>() {
-                                ips.push(ip.to_string());
-                            }
-                        }
-                    }
-                }
+        Err(e) => {
+            let failed = failures.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!("Collection failed ({} in a row): {}", failed, e);
+            if limit > 0 && failed >= limit {
+                error!("Stopping collection after {} failures in a row", failed);
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(None)
             }
         }
     }
+}
 
-    if ips.is_empty() {
-        ips.push("127.0.0.1".to_string());
-    }
+// How often a snapshot is collected when nothing stretches the interval
+const COLLECTION_PERIOD: Duration = Duration::from_secs(2);
+
+// How far past the configured period a collection may land before the
+// snapshot reports `falling_behind`
+const FALLING_BEHIND_FACTOR: f64 = 1.5;
 
-    ips
+// Scale `period` by a random factor within ±`percent`
+fn jittered(period: Duration, percent: f64) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+
+    // RandomState is randomly seeded per instance, which is plenty for
+    // spreading out a schedule without pulling in a RNG crate
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    let unit = random as f64 / u64::MAX as f64; // 0.0..=1.0
+    period.mul_f64(1.0 + (unit * 2.0 - 1.0) * percent / 100.0)
 }
 
-// Get Raspberry Pi model information
-fn get_pi_model() -> Option<String> {
-    // Try reading from /proc/device-tree/model first
-    if let Ok(model) = fs::read_to_string("/proc/device-tree/model") {
-        let cleaned = model.trim_end_matches('\0').trim();
-        if !cleaned.is_empty() {
-            return Some(cleaned.to_string());
-        }
-    }
+// Longest the collection interval is stretched to under load, as a multiple
+const MAX_LOAD_BACKOFF: u32 = 5;
 
-    // Fallback: read from /proc/cpuinfo
-    if let Ok(cpuinfo) = fs::read_to_string("/proc/cpuinfo") {
-        for line in cpuinfo.lines() {
-            if line.starts_with("Model") {
-                if let Some(model) = line.split_once(':') {
-                    return Some(model.1.trim().to_string());
-                }
-            }
-        }
+// How many times the normal interval to wait between collections: grows by
+// one step for each multiple of the load limit (threshold × cores) reached
+fn load_backoff_factor(load_avg: f64, threshold: f64) -> u32 {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let limit = threshold * cores as f64;
+    if limit <= 0.0 || load_avg <= limit {
+        return 1;
     }
-
-    None
+    ((load_avg / limit).ceil() as u32).clamp(1, MAX_LOAD_BACKOFF)
 }
 
-// Read CPU temperature from Raspberry Pi thermal zone
-fn read_cpu_temperature() -> Result<f32, std::io::Error> {
-    // Pi-specific temperature paths in order of preference
-    let temp_paths = [
-        "/sys/class/thermal/thermal_zone0/temp", // Most common
-        "/sys/devices/virtual/thermal/thermal_zone0/temp", // Alternative path
-        "/sys/class/hwmon/hwmon0/temp1_input",   // Hardware monitor
-        "/sys/class/hwmon/hwmon1/temp1_input",   // Secondary hwmon
-    ];
-
-    // Try Pi-specific paths first
-    for path in &temp_paths {
-        if let Ok(temp_str) = fs::read_to_string(path) {
-            if let Ok(temp_millidegrees) = temp_str.trim().parse::<i32>() {
-                let temp_celsius = temp_millidegrees as f32 / 1000.0;
-                // Sanity check: temperature should be reasonable (0-100°C)
-                if temp_celsius > 0.0 && temp_celsius < 100.0 {
-                    return Ok(temp_celsius);
-                }
-            }
+// Run the on-snapshot command through the shell, killing it on timeout
+async fn run_snapshot_hook(command: &str, config: &Config, snapshot: &SystemSnapshot) {
+    use tokio::io::AsyncWriteExt;
+
+    let stdin = if config.on_snapshot_stdin {
+        std::process::Stdio::piped()
+    } else {
+        std::process::Stdio::null()
+    };
+    let mut child = match tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(stdin)
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to start snapshot hook: {}", e);
+            return;
         }
-    }
+    };
 
-    // Try vcgencmd (Raspberry Pi specific)
-    if let Ok(output) = Command::new("vcgencmd").arg("measure_temp").output() {
-        if output.status.success() {
-            let temp_output = String::from_utf8_lossy(&output.stdout);
-            // Parse "temp=XX.X'C" format
-            if let Some(start) = temp_output.find("temp=") {
-                let temp_part = &temp_output[start + 5..];
-                if let Some(end) = temp_part.find("'") {
-                    let temp_str = &temp_part[..end];
-                    if let Ok(temp) = temp_str.parse::<f32>() {
-                        if temp > 0.0 && temp < 100.0 {
-                            return Ok(temp);
-                        }
-                    }
-                }
-            }
+    let run = async {
+        if let Some(mut stdin) = child.stdin.take() {
+            let json = serde_json::to_vec(snapshot).unwrap_or_default();
+            // The hook may exit without reading its input
+            let _ = stdin.write_all(&json).await;
         }
+        child.wait().await
+    };
+    match tokio::time::timeout(config.on_snapshot_timeout, run).await {
+        Ok(Ok(status)) if !status.success() => warn!("Snapshot hook exited with {}", status),
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => warn!("Snapshot hook failed: {}", e),
+        Err(_) => warn!(
+            "Snapshot hook timed out after {:?} and was killed",
+            config.on_snapshot_timeout
+        ),
     }
+}
 
-    // Final fallback: try other thermal zones
-    for i in 0..10 {
-        let path = format!("/sys/class/thermal/thermal_zone{}/temp", i);
-        if let Ok(temp_str) = fs::read_to_string(&path) {
-            if let Ok(temp_millidegrees) = temp_str.trim().parse::<i32>() {
-                let temp_celsius = temp_millidegrees as f32 / 1000.0;
-                if temp_celsius > 0.0 && temp_celsius < 100.0 {
-                    return Ok(temp_celsius);
-                }
-            }
+// Write a signal-triggered dump to `<dir>/<kind>-<timestamp>.json`, or log it
+// when no directory is configured
+async fn dump_json(
+    kind: &str,
+    timestamp: u64,
+    value: &impl Serialize,
+    dir: Option<&std::path::Path>,
+) {
+    let json = match serde_json::to_string_pretty(value) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("Failed to encode {} dump: {}", kind, e);
+            return;
         }
+    };
+    let Some(dir) = dir else {
+        info!("{} dump:\n{}", kind, json);
+        return;
+    };
+    let path = dir.join(format!("{}-{}.json", kind, timestamp));
+    match tokio::fs::write(&path, json).await {
+        Ok(()) => info!("Wrote {} dump to {}", kind, path.display()),
+        Err(e) => warn!("Failed to write {}: {}", path.display(), e),
     }
-
-    Err(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "No valid thermal zone found",
-    ))
 }
 
-// API endpoint for metrics
-async fn get_metrics(State(state): State<AppState>) -> Json<SystemSnapshot> {
-    let snapshot = state.latest_snapshot.read().await.clone();
-    Json(snapshot)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collection_recovers_after_transient_failures() {
+        let failures = AtomicU32::new(0);
+        let mut results = vec![
+            Err(anyhow::anyhow!("vcgencmd busy")),
+            Err(anyhow::anyhow!("vcgencmd busy")),
+            Ok(SystemSnapshot::default()),
+        ]
+        .into_iter();
+        let mut mock_collector = || results.next().unwrap();
+
+        assert!(matches!(
+            attempt_collection(&failures, 10, &mut mock_collector),
+            ControlFlow::Continue(None)
+        ));
+        assert!(matches!(
+            attempt_collection(&failures, 10, &mut mock_collector),
+            ControlFlow::Continue(None)
+        ));
+        assert_eq!(failures.load(Ordering::Relaxed), 2);
+        assert!(matches!(
+            attempt_collection(&failures, 10, &mut mock_collector),
+            ControlFlow::Continue(Some(_))
+        ));
+        assert_eq!(failures.load(Ordering::Relaxed), 0);
+    }
 
-// Dashboard HTML
-async fn dashboard() -> Html<&'static str> {
-    Html(include_str!("../static/index.html"))
+    #[test]
+    fn collection_gives_up_after_consecutive_failures() {
+        let failures = AtomicU32::new(0);
+        let failing = || Err(anyhow::anyhow!("every section failed"));
+        assert!(matches!(
+            attempt_collection(&failures, 2, failing),
+            ControlFlow::Continue(None)
+        ));
+        assert!(matches!(
+            attempt_collection(&failures, 2, failing),
+            ControlFlow::Break(())
+        ));
+        // 0 keeps retrying
+        assert!(matches!(
+            attempt_collection(&failures, 0, failing),
+            ControlFlow::Continue(None)
+        ));
+    }
 }
@@ -0,0 +1,1074 @@
+//! The snapshot published to clients and the types it is made of
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
+
+// Version of the serialized snapshot shape.
+//
+// Bump this when a field is renamed, removed or changes type. Adding a field
+// does not need a bump: missing fields deserialize to their defaults. When a
+// field is renamed, keep the old name as `#[serde(alias = "...")]` so older
+// payloads still deserialize.
+pub const SCHEMA_VERSION: u32 = 1;
+
+// System metrics snapshot
+//
+// Missing fields deserialize to their defaults so that payloads produced with
+// the `compact-json` feature (which omits empty/None fields) still parse.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SystemSnapshot {
+    pub schema_version: u32, // 0 for payloads produced before versioning
+    pub timestamp: u64,      // milliseconds since epoch
+    // Increases by one per collection, so reconnecting clients can spot gaps
+    // and backfill them from /api/snapshots/recent. Restarts at 0 with the monitor.
+    pub sequence: u64,
+    // Which collector produced this snapshot: "system" unless SOURCE_NAME is set
+    pub source: String,
+    // Same instant as `timestamp`, e.g. "2024-05-01T12:00:00.000Z"
+    pub timestamp_rfc3339: String,
+    // Time between the earliest and latest of the CPU, memory and load
+    // readings, leaving out sections turned off; see CONSISTENT_READS
+    pub collection_skew_ms: f64,
+    // Milliseconds taken by each sysinfo refresh ("refresh_all" or
+    // "refresh_selected", "cpu", "disks", "networks"), only with PROFILE_REFRESH
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub refresh_timings: BTreeMap<String, f64>,
+    // How each averaged or rate field was measured, keyed by field path
+    // (e.g. "cpu_usage", "load_avg_5m"), so charts can label values correctly
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub measurement_windows: BTreeMap<String, MeasurementWindow>,
+    // Time since the previous snapshot, to show sampling jitter
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub actual_interval_ms: Option<u64>,
+    // actual_interval_ms exceeded FALLING_BEHIND_FACTOR times the configured
+    // period (plus any jitter): collection can't keep up with the cadence
+    pub falling_behind: bool,
+    pub cpu_usage: f32,
+    // Online CPUs (logical cores)
+    pub cpu_cores: usize,
+    // Per-core usage in percent, unless CORE_USAGE is "summary"
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub core_usage: Vec<f32>,
+    // Spread of per-core usage, unless CORE_USAGE is "full"
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub core_usage_summary: Option<CoreUsageSummary>,
+    // Share of time spent at each CPU frequency since the previous snapshot,
+    // from cpufreq stats; low clocks here while busy point to throttling
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub cpu_frequency_residency: Vec<FrequencyResidency>,
+    // Current clock of cpu0 from cpufreq; None without cpufreq
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub cpu_frequency_mhz: Option<u32>,
+    // Current clock of each present core, indexed by CPU number; cores can
+    // differ on e.g. the Pi 5. 0 for an offline core or one without cpufreq.
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub core_frequencies_mhz: Vec<u32>,
+    pub cpu_temp: f32,
+    // Room temperature from the MQTT_AMBIENT_TOPIC sensor; None without a
+    // reading newer than MQTT_AMBIENT_MAX_AGE_SECS
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub ambient_celsius: Option<f32>,
+    // Temperature the CPU is considered critical at: the thermal zone's
+    // critical trip point, or THERMAL_CRITICAL_TEMP when there is none
+    pub thermal_critical_temp: f32,
+    // How far below critical the CPU is, as a share of the critical
+    // temperature; 100 at 0°C, 0 at or above critical. None without a reading
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub thermal_headroom_percent: Option<f32>,
+    // Cooling the kernel has engaged (fans, CPU frequency limits)
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub cooling_devices: Vec<CoolingDevice>,
+    // 1-Wire DS18B20 probes in °C, keyed by sensor id (e.g. "28-3c01d607d1a2")
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub external_sensors: BTreeMap<String, f32>,
+    // Rail voltages from vcgencmd, refreshed on the slow schedule
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub voltages: Option<Voltages>,
+    // RAM split between the ARM cores and the GPU, from vcgencmd, refreshed
+    // on the slow schedule
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub gpu_memory: Option<GpuMemoryInfo>,
+    // Firmware throttling flags plus session counters, Pi only
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub throttle: Option<ThrottleInfo>,
+    pub memory_total: u64,
+    pub memory_used: u64,
+    pub memory_percent: f32,
+    pub swap_total: u64,
+    pub swap_used: u64,
+    // Compressed RAM block devices; when used as swap, swap_used counts
+    // uncompressed pages while RAM actually holds the compressed size
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub zram: Vec<ZramDevice>,
+    // Cumulative OOM-killer invocations since boot, from /proc/vmstat
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub memory_oom_kills: Option<u64>,
+    pub memory_oom_kills_per_minute: f64,
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub vm_settings: Option<VmSettings>,
+    pub disk_total: u64,
+    pub disk_used: u64,
+    pub disk_percent: f32,
+    // Mount options of the root filesystem (e.g. "rw", "noatime")
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub disk_mount_options: Vec<String>,
+    // Likely SD card or disk trouble: kernel I/O and filesystem errors or a
+    // read-only root. None when the kernel log can't be read (dmesg often
+    // needs root) and the root filesystem looks fine.
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub storage_degraded: Option<bool>,
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub storage_health_warnings: Vec<String>,
+    // Mount points currently over their DISK_ALERTS limit
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub disk_alerts: Vec<DiskAlert>,
+    // systemd-journald disk usage; None when journald keeps no journal files
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub logging: Option<LoggingInfo>,
+    // Block device holding the root filesystem
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub disk_device: Option<StorageDevice>,
+    pub network_rx: u64,
+    pub network_tx: u64,
+    // Per-interface breakdown of the totals above, sorted by name
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub network_interfaces: Vec<NetworkInterface>,
+    // System information
+    pub hostname: String,
+    pub os_name: String,
+    pub kernel_version: String,
+    pub uptime: u64, // seconds
+    // Clock synchronization from chrony or systemd-timesyncd, refreshed on the slow schedule
+    pub time_sync: TimeSync,
+    // Names of systemd units in the failed state, refreshed on the slow
+    // schedule. None without the systemd feature or a reachable system bus
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub failed_units: Option<Vec<String>>,
+    // Cumulative idle time summed across all cores, from /proc/uptime
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub idle_seconds: Option<f64>,
+    // Average CPU utilization since boot, derived from idle time
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub cpu_usage_since_boot: Option<f32>,
+    pub load_avg_1m: f64,
+    pub load_avg_5m: f64,
+    pub load_avg_15m: f64,
+    pub current_user: String,
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub local_ips: Vec<String>,
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub pi_model: Option<String>,
+    // CPU identification and feature flags from /proc/cpuinfo
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub cpu_info: Option<CpuInfo>,
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub serial_number: Option<String>,
+    pub is_raspberry_pi: bool,
+    // "baremetal", "vm", "docker", "podman", "kubernetes", "lxc", "container" or "unknown"
+    pub environment: String,
+    pub boot_info: BootInfo,
+    // Kernel-reported CPU vulnerabilities and their mitigations
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub cpu_security: Vec<CpuVulnerability>,
+    // Overclock and memory settings from the firmware's config.txt
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub boot_config: Option<BootConfig>,
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub poe_hat: Option<PoeHatInfo>,
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub firmware: Option<FirmwareInfo>,
+    // DRM display connectors (HDMI, DSI, composite)
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub displays: Vec<DisplayInfo>,
+    // Attached USB devices and port power, only when COLLECT_USB is enabled
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub usb: Option<UsbInfo>,
+    // Busiest interrupt sources, only when COLLECT_INTERRUPTS is enabled
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub interrupts: Vec<InterruptRate>,
+    // User-defined tags from the LABELS setting
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub labels: BTreeMap<String, String>,
+    // User-configured values; null when the source could not be read or parsed
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub custom: BTreeMap<String, serde_json::Value>,
+    // Sections that could not be read this time; their fields hold defaults
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub collection_errors: Vec<CollectionError>,
+    // Sections emptied to keep the snapshot under MAX_SNAPSHOT_BYTES
+    #[cfg_attr(feature = "compact-json", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub truncated_sections: Vec<String>,
+}
+
+// Fields left out of SystemSnapshot::content_hash because they change with
+// every collection even when nothing else did. `uptime` only restates
+// `timestamp` relative to `boot_info.boot_time`, and `measurement_windows`
+// holds the time since the previous snapshot.
+pub const SNAPSHOT_CLOCK_FIELDS: &[&str] = &[
+    "timestamp",
+    "timestamp_rfc3339",
+    "sequence",
+    "actual_interval_ms",
+    "falling_behind",
+    "collection_skew_ms",
+    "refresh_timings",
+    "measurement_windows",
+    "uptime",
+];
+
+pub type ClearSection = fn(&mut SystemSnapshot);
+
+// Sections emptied, in this order, while a snapshot is over MAX_SNAPSHOT_BYTES:
+// long lists of detail first, then descriptive sections. The top-level gauges
+// are never dropped.
+pub const EXPENDABLE_SECTIONS: &[(&str, ClearSection)] = &[
+    ("interrupts", |s| s.interrupts.clear()),
+    ("custom", |s| s.custom.clear()),
+    ("cpu_security", |s| s.cpu_security.clear()),
+    ("external_sensors", |s| s.external_sensors.clear()),
+    ("displays", |s| s.displays.clear()),
+    ("cooling_devices", |s| s.cooling_devices.clear()),
+    ("zram", |s| s.zram.clear()),
+    ("refresh_timings", |s| s.refresh_timings.clear()),
+    ("cpu_frequency_residency", |s| {
+        s.cpu_frequency_residency.clear()
+    }),
+    ("boot_config", |s| s.boot_config = None),
+    ("cpu_info", |s| s.cpu_info = None),
+    ("firmware", |s| s.firmware = None),
+    ("network_interfaces", |s| s.network_interfaces.clear()),
+    ("core_usage", |s| s.core_usage.clear()),
+    ("labels", |s| s.labels.clear()),
+];
+
+impl SystemSnapshot {
+    // Empty expendable sections until the JSON encoding fits in `max_bytes`,
+    // listing each one in `truncated_sections`. May still be over the limit
+    // once everything expendable is gone. 0 means no limit.
+    pub fn truncate_to(&mut self, max_bytes: usize) {
+        if max_bytes == 0 {
+            return;
+        }
+        let encoded_len =
+            |snapshot: &Self| serde_json::to_vec(snapshot).map_or(0, |json| json.len());
+        let mut len = encoded_len(self);
+        for &(section, clear) in EXPENDABLE_SECTIONS {
+            if len <= max_bytes {
+                return;
+            }
+            clear(self);
+            // Sections that were already empty aren't worth flagging
+            if encoded_len(self) < len {
+                self.truncated_sections.push(section.to_string());
+                len = encoded_len(self);
+            }
+        }
+    }
+
+    // Stable 64-bit hash of what the snapshot measured, for ETags and spotting
+    // repeated states. Covers every serialized field except the ones describing
+    // when and how this particular snapshot was taken: SNAPSHOT_CLOCK_FIELDS.
+    // Uses FNV-1a over the JSON encoding (object keys sorted), so the value
+    // survives a round trip through JSON and is the same across restarts and
+    // Rust releases, unlike std's DefaultHasher.
+    pub fn content_hash(&self) -> u64 {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            for field in SNAPSHOT_CLOCK_FIELDS {
+                fields.remove(*field);
+            }
+        }
+        let encoded = serde_json::to_vec(&value).unwrap_or_default();
+        encoded.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    // Whether two snapshots show the same state: gauges within `tolerance`
+    // (percent, °C or load) and identical identity fields. Timestamps and
+    // ever-increasing counters such as network bytes are ignored.
+    pub fn materially_equal(&self, other: &Self, tolerance: f64) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() <= tolerance;
+        close(self.cpu_usage.into(), other.cpu_usage.into())
+            && close(self.cpu_temp.into(), other.cpu_temp.into())
+            && close(self.memory_percent.into(), other.memory_percent.into())
+            && close(self.disk_percent.into(), other.disk_percent.into())
+            && close(self.load_avg_1m, other.load_avg_1m)
+            && self.hostname == other.hostname
+            && self.local_ips == other.local_ips
+            && self.memory_oom_kills == other.memory_oom_kills
+            && self
+                .disk_alerts
+                .iter()
+                .map(|alert| &alert.mount_point)
+                .eq(other.disk_alerts.iter().map(|alert| &alert.mount_point))
+    }
+}
+
+// Unit and JSON type of each numeric snapshot field, served by /api/units so
+// clients can format values without hardcoding them. Keep in step with the
+// structs above; `[]` marks array elements and `*` map values.
+pub const FIELD_UNITS: &[(&str, &str, &str)] = &[
+    ("schema_version", "version", "integer"),
+    ("timestamp", "milliseconds_since_epoch", "integer"),
+    ("sequence", "count", "integer"),
+    ("actual_interval_ms", "milliseconds", "integer"),
+    ("collection_skew_ms", "milliseconds", "float"),
+    ("refresh_timings.*", "milliseconds", "float"),
+    ("measurement_windows.*.window_ms", "milliseconds", "integer"),
+    ("cpu_usage", "percent", "float"),
+    ("cpu_cores", "count", "integer"),
+    ("core_usage[]", "percent", "float"),
+    ("core_usage_summary.min", "percent", "float"),
+    ("core_usage_summary.max", "percent", "float"),
+    ("core_usage_summary.mean", "percent", "float"),
+    (
+        "cpu_frequency_residency[].frequency_mhz",
+        "megahertz",
+        "integer",
+    ),
+    ("cpu_frequency_residency[].percent", "percent", "float"),
+    ("cpu_frequency_mhz", "megahertz", "integer"),
+    ("core_frequencies_mhz[]", "megahertz", "integer"),
+    ("cpu_temp", "celsius", "float"),
+    ("ambient_celsius", "celsius", "float"),
+    ("thermal_critical_temp", "celsius", "float"),
+    ("thermal_headroom_percent", "percent", "float"),
+    ("cooling_devices[].cur_state", "level", "integer"),
+    ("cooling_devices[].max_state", "level", "integer"),
+    ("cooling_devices[].duty_percent", "percent", "float"),
+    ("cooling_devices[].rpm", "rpm", "integer"),
+    ("external_sensors.*", "celsius", "float"),
+    ("voltages.core", "volts", "float"),
+    ("voltages.sdram_c", "volts", "float"),
+    ("voltages.sdram_i", "volts", "float"),
+    ("voltages.sdram_p", "volts", "float"),
+    ("gpu_memory.arm_bytes", "bytes", "integer"),
+    ("gpu_memory.gpu_bytes", "bytes", "integer"),
+    ("gpu_memory.malloc_used_bytes", "bytes", "integer"),
+    ("throttle.raw", "bitmask", "integer"),
+    ("throttle.throttle_events", "count", "integer"),
+    ("throttle.throttled_seconds", "seconds", "float"),
+    ("memory_total", "bytes", "integer"),
+    ("memory_used", "bytes", "integer"),
+    ("memory_percent", "percent", "float"),
+    ("swap_total", "bytes", "integer"),
+    ("swap_used", "bytes", "integer"),
+    ("zram[].disk_size", "bytes", "integer"),
+    ("zram[].original_bytes", "bytes", "integer"),
+    ("zram[].compressed_bytes", "bytes", "integer"),
+    ("zram[].memory_used_bytes", "bytes", "integer"),
+    ("zram[].compression_ratio", "ratio", "float"),
+    ("memory_oom_kills", "count", "integer"),
+    ("memory_oom_kills_per_minute", "per_minute", "float"),
+    ("vm_settings.swappiness", "level", "integer"),
+    ("vm_settings.overcommit_ratio", "percent", "integer"),
+    ("disk_total", "bytes", "integer"),
+    ("disk_used", "bytes", "integer"),
+    ("disk_percent", "percent", "float"),
+    ("disk_alerts[].percent", "percent", "float"),
+    ("disk_alerts[].max_percent", "percent", "float"),
+    ("logging.journal_bytes", "bytes", "integer"),
+    (
+        "logging.journal_growth_bytes_per_hour",
+        "bytes_per_hour",
+        "float",
+    ),
+    ("network_rx", "bytes", "integer"),
+    ("network_tx", "bytes", "integer"),
+    ("network_interfaces[].rx_bytes", "bytes", "integer"),
+    ("network_interfaces[].tx_bytes", "bytes", "integer"),
+    (
+        "network_interfaces[].rx_bytes_since_boot",
+        "bytes",
+        "integer",
+    ),
+    (
+        "network_interfaces[].tx_bytes_since_boot",
+        "bytes",
+        "integer",
+    ),
+    ("network_interfaces[].rx_bytes_session", "bytes", "integer"),
+    ("network_interfaces[].tx_bytes_session", "bytes", "integer"),
+    ("network_interfaces[].mtu", "bytes", "integer"),
+    ("network_interfaces[].tx_queue_len", "packets", "integer"),
+    (
+        "network_interfaces[].rx_bytes_per_sec",
+        "bytes_per_second",
+        "float",
+    ),
+    (
+        "network_interfaces[].tx_bytes_per_sec",
+        "bytes_per_second",
+        "float",
+    ),
+    (
+        "network_interfaces[].seconds_since_last_activity",
+        "seconds",
+        "float",
+    ),
+    (
+        "cpu_info.available_frequencies_mhz[]",
+        "megahertz",
+        "integer",
+    ),
+    ("uptime", "seconds", "integer"),
+    ("time_sync.offset_ms", "milliseconds", "float"),
+    ("idle_seconds", "seconds", "float"),
+    ("cpu_usage_since_boot", "percent", "float"),
+    ("load_avg_1m", "load", "float"),
+    ("load_avg_5m", "load", "float"),
+    ("load_avg_15m", "load", "float"),
+    ("boot_info.boot_time", "seconds_since_epoch", "integer"),
+    ("poe_hat.fan_state", "level", "integer"),
+    ("poe_hat.fan_max_state", "level", "integer"),
+    ("poe_hat.current_max_ma", "milliamps", "integer"),
+    ("usb.devices[].speed_mbps", "megabits_per_second", "float"),
+    ("usb.devices[].max_power_ma", "milliamps", "integer"),
+    ("usb.over_current_count", "count", "integer"),
+    ("interrupts[].total", "count", "integer"),
+    ("interrupts[].per_second", "per_second", "float"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldUnit {
+    pub unit: &'static str,
+    #[serde(rename = "type")]
+    pub value_type: &'static str,
+}
+
+// One file from /sys/devices/system/cpu/vulnerabilities
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CpuVulnerability {
+    pub name: String,   // e.g. "spectre_v2"
+    pub status: String, // e.g. "Not affected", "Mitigation: __user pointer sanitization"
+}
+
+// Firmware boot settings, as read from config.txt when the monitor started
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootConfig {
+    pub path: String, // "/boot/firmware/config.txt" or "/boot/config.txt"
+    // Only the keys in BOOT_CONFIG_KEYS that are set, e.g. "gpu_mem" => "128"
+    pub settings: BTreeMap<String, String>,
+}
+
+// config.txt settings that affect clocks, voltages and memory split
+pub const BOOT_CONFIG_KEYS: &[&str] = &[
+    "arm_64bit",
+    "arm_boost",
+    "arm_freq",
+    "arm_freq_min",
+    "core_freq",
+    "force_turbo",
+    "gpu_freq",
+    "gpu_mem",
+    "initial_turbo",
+    "over_voltage",
+    "over_voltage_delta",
+    "sdram_freq",
+    "temp_limit",
+    "temp_soft_limit",
+    "total_mem",
+];
+
+// CPU identification from /proc/cpuinfo (first core; Pi cores are identical)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CpuInfo {
+    // e.g. ["fp", "asimd", "crc32", "cpuid"]; x86 "flags" elsewhere
+    pub features: Vec<String>,
+    pub implementer: Option<String>, // e.g. "0x41" (Arm)
+    pub part: Option<String>,        // e.g. "0xd08"
+    pub variant: Option<String>,
+    pub revision: Option<String>,
+    // Decoded from implementer and part, e.g. "Cortex-A72"
+    pub part_name: Option<String>,
+    // Clock steps cpufreq can pick from, lowest first. Empty when the driver
+    // only takes a range (e.g. intel_pstate) or there is no cpufreq.
+    pub available_frequencies_mhz: Vec<u32>,
+}
+
+// A snapshot section that failed, e.g. section "temperature"
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CollectionError {
+    pub section: String,
+    pub error: String,
+}
+
+// Boot time and how the previous session ended
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootInfo {
+    pub boot_time: u64, // seconds since epoch
+    pub previous_shutdown: ShutdownKind,
+}
+
+// Thermal cooling device from /sys/class/thermal/cooling_deviceN
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoolingDevice {
+    pub name: String,        // e.g. "cooling_device0"
+    pub device_type: String, // raw driver type, e.g. "pwm-fan", "cpufreq-cpu0"
+    pub kind: CoolingKind,
+    pub cur_state: u32,
+    pub max_state: u32,
+    // Fans only: the duty cycle applied (0-100) and the speed it results in.
+    // Duty comes from the fan's hwmon pwm1, or the cooling level when there is none.
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub duty_percent: Option<f32>,
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub rpm: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoolingKind {
+    Fan,
+    Processor,
+    #[default]
+    Other,
+}
+
+impl CoolingKind {
+    pub fn from_device_type(device_type: &str) -> Self {
+        let lower = device_type.to_ascii_lowercase();
+        if lower.contains("fan") {
+            Self::Fan
+        } else if lower == "processor" || lower.contains("cpu") {
+            Self::Processor
+        } else {
+            Self::Other
+        }
+    }
+}
+
+// Decoded firmware throttling flags (vcgencmd get_throttled bit layout)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThrottleInfo {
+    pub raw: u32,
+    pub source: String, // "sysfs" or "vcgencmd"
+    pub under_voltage: bool,
+    pub freq_capped: bool,
+    pub throttled: bool,
+    pub soft_temp_limit: bool,
+    // Sticky flags: the condition happened at some point since boot
+    pub under_voltage_occurred: bool,
+    pub freq_capped_occurred: bool,
+    pub throttled_occurred: bool,
+    pub soft_temp_limit_occurred: bool,
+    // Counted by the monitor since it started, as the firmware only
+    // reports the current state and sticky bits
+    pub throttle_events: u64,
+    pub throttled_seconds: f64,
+}
+
+impl ThrottleInfo {
+    pub fn from_flags(raw: u32, source: &str) -> Self {
+        let bit = |n: u32| raw & (1 << n) != 0;
+        Self {
+            raw,
+            source: source.to_string(),
+            under_voltage: bit(0),
+            freq_capped: bit(1),
+            throttled: bit(2),
+            soft_temp_limit: bit(3),
+            under_voltage_occurred: bit(16),
+            freq_capped_occurred: bit(17),
+            throttled_occurred: bit(18),
+            soft_temp_limit_occurred: bit(19),
+            throttle_events: 0,
+            throttled_seconds: 0.0,
+        }
+    }
+
+    // Throttled or frequency capped right now
+    pub fn is_limited(&self) -> bool {
+        self.throttled || self.freq_capped
+    }
+}
+
+// The span a non-instantaneous value was averaged over
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MeasurementWindow {
+    pub method: AveragingMethod,
+    // Window length, the time since the previous snapshot, or the time
+    // constant for exponential averages. None before there is a previous snapshot.
+    pub window_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AveragingMethod {
+    // Averaged over a fixed window
+    #[default]
+    Window,
+    // Change since the previous snapshot
+    SincePrevious,
+    // Exponentially damped moving average, like the kernel's load averages
+    Exponential,
+}
+
+impl MeasurementWindow {
+    pub fn new(method: AveragingMethod, window: Option<Duration>) -> Self {
+        Self {
+            method,
+            window_ms: window.map(|window| window.as_millis() as u64),
+        }
+    }
+}
+
+// Per-core usage condensed for boards with many cores
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CoreUsageSummary {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+impl CoreUsageSummary {
+    pub fn from_usage(usage: &[f32]) -> Option<Self> {
+        if usage.is_empty() {
+            return None;
+        }
+        Some(Self {
+            min: usage.iter().copied().fold(f32::INFINITY, f32::min),
+            max: usage.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            mean: usage.iter().sum::<f32>() / usage.len() as f32,
+        })
+    }
+}
+
+// Time spent at one CPU frequency
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FrequencyResidency {
+    pub frequency_mhz: u32,
+    pub percent: f32,
+}
+
+// Network interface counters and link settings from /sys/class/net/<name>
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    pub name: String, // e.g. "eth0", "wlan0"
+    // Network namespace from NETWORK_NAMESPACES; None for the host. Only
+    // byte counters are read inside other namespaces.
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub namespace: Option<String>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    // Kernel counters from /proc/net/dev, which only reset on reboot (or when
+    // the interface is recreated)
+    pub rx_bytes_since_boot: Option<u64>,
+    pub tx_bytes_since_boot: Option<u64>,
+    // Bytes since the monitor started, zeroed on every restart
+    pub rx_bytes_session: u64,
+    pub tx_bytes_session: u64,
+    pub mtu: Option<u32>,
+    pub tx_queue_len: Option<u32>, // txqueuelen, in packets
+    pub addresses: Vec<String>,
+    // Throughput since the previous snapshot; 0 on the first snapshot and
+    // when the counters went backwards because the interface was recreated
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+    // Time since either byte counter last moved, or since the monitor found
+    // the interface if they never have; growing on an interface that should
+    // be busy means it is up but passing no traffic. None on the first
+    // snapshot that includes the interface.
+    #[cfg_attr(
+        feature = "compact-json",
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub seconds_since_last_activity: Option<f64>,
+}
+
+// A mount point over its configured usage limit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskAlert {
+    pub mount_point: String,
+    pub percent: f32,
+    pub max_percent: f32,
+}
+
+// systemd-journald storage, from the journal files it keeps
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoggingInfo {
+    // "/var/log/journal" (persistent) or "/run/log/journal" (RAM only)
+    pub journal_dir: String,
+    // Space taken on disk, as `journalctl --disk-usage` reports it
+    pub journal_bytes: u64,
+    // Growth over the last JOURNAL_GROWTH_WINDOW; None until there are two readings
+    pub journal_growth_bytes_per_hour: Option<f64>,
+}
+
+// Block device characteristics from /sys/block/<dev>
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageDevice {
+    pub name: String,          // whole disk, e.g. "mmcblk0", "sda", "nvme0n1"
+    pub model: Option<String>, // e.g. "SC16G" for an SD card
+    // Spinning disk; false for SD cards, USB flash and SSDs
+    pub is_rotational: bool,
+}
+
+// Supply rail voltages from `vcgencmd measure_volts`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Voltages {
+    pub core: Option<f32>,
+    pub sdram_c: Option<f32>, // SDRAM controller
+    pub sdram_i: Option<f32>, // SDRAM I/O
+    pub sdram_p: Option<f32>, // SDRAM PHY
+}
+
+// Memory split from `vcgencmd get_mem`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GpuMemoryInfo {
+    pub arm_bytes: u64, // addressable by Linux
+    pub gpu_bytes: u64, // reserved for the VideoCore (gpu_mem in config.txt)
+    // In use on the GPU's malloc heap, which camera and codec buffers come
+    // from; None when the firmware doesn't report it
+    pub malloc_used_bytes: Option<u64>,
+}
+
+// zram device from /sys/block/zramN
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZramDevice {
+    pub name: String,      // e.g. "zram0"
+    pub algorithm: String, // e.g. "lz4", "zstd"
+    pub is_swap: bool,
+    pub disk_size: u64, // uncompressed capacity
+    pub original_bytes: u64,
+    pub compressed_bytes: u64,
+    // RAM taken including allocator overhead
+    pub memory_used_bytes: u64,
+    // original / compressed; None while empty
+    pub compression_ratio: Option<f32>,
+}
+
+// Kernel memory management knobs from /proc/sys/vm
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VmSettings {
+    pub swappiness: Option<u32>,
+    pub overcommit_memory: Option<u32>, // 0 = heuristic, 1 = always, 2 = never
+    pub overcommit_ratio: Option<u32>,  // percent, used when overcommit_memory = 2
+}
+
+// VideoCore firmware build and enabled hardware codecs, from vcgencmd
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FirmwareInfo {
+    pub version: String,    // firmware commit hash
+    pub build_date: String, // e.g. "Mar 17 2023 10:50:39"
+    pub bootloader_version: Option<String>,
+    pub codecs_enabled: Vec<String>,
+}
+
+// Display connector from /sys/class/drm
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DisplayInfo {
+    pub connector: String, // e.g. "card1-HDMI-A-1"
+    pub connected: bool,
+    pub enabled: bool,
+    // Preferred mode reported by the display, e.g. "1920x1080"
+    pub resolution: Option<String>,
+}
+
+// USB devices from /sys/bus/usb/devices plus port power limits
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsbInfo {
+    pub devices: Vec<UsbDevice>,
+    // Over-current events summed across hub ports since boot; None without port info
+    pub over_current_count: Option<u64>,
+    // Pi 5: whether the firmware lets the USB ports draw 1.6A in total rather
+    // than 600mA, set for 5A supplies or with usb_max_current_enable=1
+    pub max_current_enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsbDevice {
+    pub bus_id: String,     // position in the tree, e.g. "1-1.3"
+    pub vendor_id: String,  // e.g. "0781"
+    pub product_id: String, // e.g. "5583"
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    // Negotiated speed: 1.5, 12, 480, 5000, ...
+    pub speed_mbps: Option<f32>,
+    // Most the device said it will draw
+    pub max_power_ma: Option<u32>,
+}
+
+// Official PoE/PoE+ HAT fan and power supply, present only when the HAT is fitted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PoeHatInfo {
+    pub fan_state: Option<u32>,
+    pub fan_max_state: Option<u32>,
+    pub power_online: Option<bool>,
+    pub current_max_ma: Option<u32>,
+}
+
+// Interrupt source from /proc/interrupts, summed across CPUs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InterruptRate {
+    pub irq: String,
+    pub name: String,
+    pub total: u64,
+    pub per_second: f64,
+}
+
+// Whether the clock is synchronized, and how far off it is
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimeSync {
+    pub daemon: TimeDaemon,
+    // None when the daemon can't be asked
+    pub synchronized: Option<bool>,
+    // Estimated offset from true time, positive when the clock is fast. Only
+    // chrony reports it.
+    pub offset_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeDaemon {
+    Chrony,
+    Timesyncd,
+    #[default]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownKind {
+    Clean,
+    Unexpected,
+    #[default]
+    Unknown,
+}
+
+// Snapshot fields that can identify a device or its owner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactedField {
+    Hostname,
+    LocalIps,
+    SerialNumber,
+    CurrentUser,
+}
+
+impl FromStr for RedactedField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hostname" => Ok(Self::Hostname),
+            "local_ips" => Ok(Self::LocalIps),
+            "serial_number" => Ok(Self::SerialNumber),
+            "current_user" => Ok(Self::CurrentUser),
+            _ => anyhow::bail!(
+                "Cannot redact {:?}: expected hostname, local_ips, serial_number or current_user",
+                s
+            ),
+        }
+    }
+}
+
+// Placeholder shown instead of redacted values
+pub const REDACTED: &str = "***";
+
+// Mask sensitive fields before a snapshot leaves the process
+pub fn redact(snapshot: &mut SystemSnapshot, fields: &[RedactedField]) {
+    for field in fields {
+        match field {
+            RedactedField::Hostname => snapshot.hostname = REDACTED.to_string(),
+            RedactedField::LocalIps => {
+                // The same addresses also appear per interface
+                let interface_addresses = snapshot
+                    .network_interfaces
+                    .iter_mut()
+                    .flat_map(|interface| &mut interface.addresses);
+                for ip in snapshot.local_ips.iter_mut().chain(interface_addresses) {
+                    *ip = REDACTED.to_string();
+                }
+            }
+            RedactedField::SerialNumber => {
+                if let Some(serial) = &mut snapshot.serial_number {
+                    *serial = REDACTED.to_string();
+                }
+            }
+            RedactedField::CurrentUser => snapshot.current_user = REDACTED.to_string(),
+        }
+    }
+}
+
+// Format epoch milliseconds as an RFC 3339 UTC timestamp
+pub fn format_rfc3339(timestamp_ms: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp_ms as i64)
+        .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+        .unwrap_or_default()
+}
+
+// Look up a dotted path in a JSON value; numeric segments index into arrays
+#[cfg(any(feature = "charts", feature = "mqtt"))]
+pub fn json_path_number(value: &serde_json::Value, path: &str) -> Option<f64> {
+    path.split('.')
+        .try_fold(value, |value, key| {
+            value
+                .get(key)
+                .or_else(|| value.get(key.parse::<usize>().ok()?))
+        })?
+        .as_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_snapshot() -> SystemSnapshot {
+        SystemSnapshot {
+            timestamp: 1_700_000_000_000,
+            sequence: 7,
+            cpu_usage: 12.345,
+            cpu_temp: 48.7,
+            core_usage: vec![10.1, 0.3, 99.9, 33.3],
+            memory_used: 512 * 1024 * 1024,
+            memory_total: 4096 * 1024 * 1024,
+            load_avg_1m: 0.42,
+            hostname: "pi".to_string(),
+            labels: BTreeMap::from([("role".to_string(), "sensor".to_string())]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn content_hash_survives_reserialization() {
+        let snapshot = sample_snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let reparsed: SystemSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(reparsed.content_hash(), snapshot.content_hash());
+        let twice: SystemSnapshot =
+            serde_json::from_str(&serde_json::to_string(&reparsed).unwrap()).unwrap();
+        assert_eq!(twice.content_hash(), snapshot.content_hash());
+    }
+
+    #[test]
+    fn content_hash_ignores_clock_fields() {
+        let snapshot = sample_snapshot();
+        let mut later = sample_snapshot();
+        later.timestamp += 2000;
+        later.timestamp_rfc3339 = "2023-11-14T22:13:22.000Z".to_string();
+        later.sequence += 1;
+        later.uptime += 2;
+        later.actual_interval_ms = Some(2000);
+        later.measurement_windows.insert(
+            "cpu_usage".to_string(),
+            MeasurementWindow::new(AveragingMethod::SincePrevious, Some(Duration::from_secs(2))),
+        );
+        assert_eq!(later.content_hash(), snapshot.content_hash());
+
+        later.cpu_usage += 1.0;
+        assert_ne!(later.content_hash(), snapshot.content_hash());
+    }
+
+    #[test]
+    fn redacting_local_ips_masks_interface_addresses() {
+        let mut snapshot = SystemSnapshot {
+            local_ips: vec!["192.168.1.20".to_string()],
+            network_interfaces: vec![NetworkInterface {
+                name: "eth0".to_string(),
+                addresses: vec!["192.168.1.20".to_string(), "fe80::1".to_string()],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        redact(&mut snapshot, &[RedactedField::LocalIps]);
+        assert_eq!(snapshot.local_ips, [REDACTED]);
+        assert_eq!(
+            snapshot.network_interfaces[0].addresses,
+            [REDACTED, REDACTED]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! PNG charts of the snapshot history
+
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use super::AppState;
+use crate::snapshot::{json_path_number, SystemSnapshot, FIELD_UNITS};
+
+#[derive(Debug, Deserialize)]
+pub struct ChartQuery {
+    // Dotted path to a numeric snapshot field, e.g. "cpu_temp", "voltages.core"
+    // or "core_usage.0"
+    metric: String,
+    seconds: Option<u64>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+// Chart size when the request doesn't ask for one, and the largest side allowed
+const DEFAULT_CHART_SIZE: (u32, u32) = (800, 400);
+const MAX_CHART_SIDE: u32 = 2000;
+
+type ChartReader = fn(&SystemSnapshot) -> Option<f64>;
+
+// Chartable fields read straight from the snapshot, so the common metrics
+// don't need the snapshot converted to JSON
+const CHART_FIELDS: &[(&str, ChartReader)] = &[
+    ("cpu_usage", |s| Some(s.cpu_usage.into())),
+    ("cpu_temp", |s| Some(s.cpu_temp.into())),
+    ("cpu_frequency_mhz", |s| s.cpu_frequency_mhz.map(f64::from)),
+    ("ambient_celsius", |s| s.ambient_celsius.map(f64::from)),
+    ("thermal_headroom_percent", |s| {
+        s.thermal_headroom_percent.map(f64::from)
+    }),
+    ("memory_used", |s| Some(s.memory_used as f64)),
+    ("memory_percent", |s| Some(s.memory_percent.into())),
+    ("swap_used", |s| Some(s.swap_used as f64)),
+    ("memory_oom_kills_per_minute", |s| {
+        Some(s.memory_oom_kills_per_minute)
+    }),
+    ("disk_used", |s| Some(s.disk_used as f64)),
+    ("disk_percent", |s| Some(s.disk_percent.into())),
+    ("network_rx", |s| Some(s.network_rx as f64)),
+    ("network_tx", |s| Some(s.network_tx as f64)),
+    ("load_avg_1m", |s| Some(s.load_avg_1m)),
+    ("load_avg_5m", |s| Some(s.load_avg_5m)),
+    ("load_avg_15m", |s| Some(s.load_avg_15m)),
+    ("actual_interval_ms", |s| {
+        s.actual_interval_ms.map(|ms| ms as f64)
+    }),
+    ("collection_skew_ms", |s| Some(s.collection_skew_ms)),
+];
+
+// A chart metric path, resolved once per request
+enum ChartMetric {
+    Field(ChartReader),
+    Core(usize),
+    CoreFrequency(usize),
+    // Anything else is looked up in the snapshot's JSON form
+    Json(String),
+}
+
+impl ChartMetric {
+    fn resolve(path: &str) -> Self {
+        if let Some(&(_, read)) = CHART_FIELDS.iter().find(|(name, _)| *name == path) {
+            return Self::Field(read);
+        }
+        let index = |prefix: &str| path.strip_prefix(prefix)?.parse().ok();
+        if let Some(core) = index("core_usage.") {
+            return Self::Core(core);
+        }
+        if let Some(core) = index("core_frequencies_mhz.") {
+            return Self::CoreFrequency(core);
+        }
+        Self::Json(path.to_string())
+    }
+
+    fn read(&self, snapshot: &SystemSnapshot) -> Option<f64> {
+        match self {
+            Self::Field(read) => read(snapshot),
+            Self::Core(core) => snapshot.core_usage.get(*core).copied().map(f64::from),
+            Self::CoreFrequency(core) => snapshot
+                .core_frequencies_mhz
+                .get(*core)
+                .copied()
+                .map(f64::from),
+            Self::Json(path) => json_path_number(&serde_json::to_value(snapshot).ok()?, path),
+        }
+    }
+}
+
+// Unit of a chart metric from FIELD_UNITS, matching "core_usage.0" to
+// "core_usage[]"
+fn chart_unit(path: &str) -> Option<&'static str> {
+    let mut pattern = String::new();
+    for segment in path.split('.') {
+        if segment.parse::<usize>().is_ok() {
+            pattern.push_str("[]");
+        } else {
+            if !pattern.is_empty() {
+                pattern.push('.');
+            }
+            pattern.push_str(segment);
+        }
+    }
+    FIELD_UNITS
+        .iter()
+        .find(|(field, _, _)| *field == pattern)
+        .map(|&(_, unit, _)| unit)
+}
+
+// "1h 5m", "9m 58s" or "40s"
+fn format_span(seconds: u64) -> String {
+    match (seconds / 3600, seconds % 3600 / 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+// API endpoint rendering one metric from the history buffer as a PNG line
+// chart, for e-mail reports and e-ink displays. Without `seconds` it covers
+// the whole history; the caption states the span actually drawn.
+pub async fn get_chart(State(state): State<AppState>, Query(query): Query<ChartQuery>) -> Response {
+    let width = query.width.unwrap_or(DEFAULT_CHART_SIZE.0);
+    let height = query.height.unwrap_or(DEFAULT_CHART_SIZE.1);
+    if !(1..=MAX_CHART_SIDE).contains(&width) || !(1..=MAX_CHART_SIDE).contains(&height) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("width and height must be between 1 and {}", MAX_CHART_SIDE),
+        )
+            .into_response();
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let since = query.seconds.map_or(0, |seconds| {
+        now.saturating_sub(seconds.saturating_mul(1000))
+    });
+    let metric = ChartMetric::resolve(&query.metric);
+    let (points, history_starts_after) = {
+        let history = state.history.read().await;
+        let points: Vec<(u64, f64)> = history
+            .iter()
+            .filter(|snapshot| snapshot.timestamp >= since)
+            .filter_map(|snapshot| Some((snapshot.timestamp, metric.read(snapshot)?)))
+            .collect();
+        let oldest = history.front().map_or(now, |snapshot| snapshot.timestamp);
+        (points, since > 0 && oldest > since)
+    };
+    let (Some(&(first, _)), Some(&(last, _))) = (points.first(), points.last()) else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No numeric history for {:?}", query.metric),
+        )
+            .into_response();
+    };
+
+    let mut caption = format!(
+        "{}, last {}",
+        query.metric,
+        format_span(last.saturating_sub(first) / 1000)
+    );
+    if let (Some(seconds), true) = (query.seconds, history_starts_after) {
+        caption.push_str(&format!(
+            " (history holds less than {})",
+            format_span(seconds)
+        ));
+    }
+    let y_desc = chart_unit(&query.metric).unwrap_or_default().to_string();
+    match tokio::task::spawn_blocking(move || {
+        render_chart(&points, &caption, &y_desc, width, height)
+    })
+    .await
+    {
+        Ok(Ok(png)) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+// Font for chart labels, bundled so charts render on headless systems
+// without any fonts installed (see assets/DejaVuSansMono-LICENSE)
+const CHART_FONT: &[u8] = include_bytes!("../../assets/DejaVuSansMono.ttf");
+
+// Draw (timestamp ms, value) points as a line chart with a caption and
+// labelled axes, and encode it as PNG. The x axis counts seconds back from
+// the newest point.
+fn render_chart(
+    points: &[(u64, f64)],
+    caption: &str,
+    y_desc: &str,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<Vec<u8>> {
+    use plotters::prelude::*;
+
+    static REGISTER_FONT: std::sync::Once = std::sync::Once::new();
+    REGISTER_FONT.call_once(|| {
+        if plotters::style::register_font("sans-serif", FontStyle::Normal, CHART_FONT).is_err() {
+            warn!("Failed to load the chart font");
+        }
+    });
+
+    // Seconds relative to the newest point; plotters' integer ranges
+    // overflow on epoch milliseconds
+    let end = points.last().map_or(0, |&(time, _)| time);
+    let seconds = |time: u64| -(end.saturating_sub(time) as f64 / 1000.0);
+    let start = points
+        .first()
+        .map_or(0.0, |&(time, _)| seconds(time))
+        .min(-1.0);
+    let (mut low, mut high) = points.iter().fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(low, high), &(_, value)| (low.min(value), high.max(value)),
+    );
+    // Keep a flat line off the chart's edges
+    let margin = ((high - low) * 0.1).max(0.5);
+    low -= margin;
+    high += margin;
+
+    let mut pixels = vec![0u8; width as usize * height as usize * 3];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(caption, ("sans-serif", 16))
+            .margin(10)
+            .x_label_area_size(35)
+            .y_label_area_size(60)
+            .build_cartesian_2d(start..0.0, low..high)?;
+        chart
+            .configure_mesh()
+            .x_desc("seconds ago")
+            .y_desc(y_desc)
+            .x_label_formatter(&|x| format!("{:.0}", (-x).max(0.0)))
+            .label_style(("sans-serif", 12))
+            .draw()?;
+        chart.draw_series(LineSeries::new(
+            points.iter().map(|&(time, value)| (seconds(time), value)),
+            BLUE.stroke_width(2),
+        ))?;
+        root.present()?;
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(png)
+}
@@ -0,0 +1,251 @@
+//! Route handlers
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Json, Response,
+    },
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    sync::atomic::Ordering,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio_stream::{wrappers::WatchStream, Stream, StreamExt};
+use tracing::info;
+
+use super::{embedded_asset, is_authorized, AppState, Encoding, HealthStatus, StaticAssets};
+use crate::{
+    export::{prometheus_text, HistoryCsvRow, HISTORY_CSV_HEADER},
+    snapshot::{redact, FieldUnit, SystemSnapshot, FIELD_UNITS},
+};
+
+// API endpoint for metrics
+pub async fn get_metrics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let mut snapshot = state.latest_snapshot.borrow().clone();
+    redact(&mut snapshot, &state.config.redact);
+    snapshot.truncate_to(state.config.max_snapshot_bytes);
+    let encoding = Encoding::negotiate(&headers);
+    // Weak because the timestamp may differ between snapshots sharing a hash.
+    // Each encoding is a different representation, so it gets its own tag.
+    let etag = format!("W/\"{:016x}-{}\"", snapshot.content_hash(), encoding.name());
+    let unchanged = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    let mut response = if unchanged {
+        ([(header::VARY, "Accept")], StatusCode::NOT_MODIFIED).into_response()
+    } else {
+        encoding.respond(&snapshot)
+    };
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecentQuery {
+    count: Option<usize>,
+}
+
+// API endpoint for backfilling charts: the last `count` snapshots, oldest
+// first. Defaults to, and is capped at, everything in the history buffer.
+pub async fn get_recent_snapshots(
+    State(state): State<AppState>,
+    Query(query): Query<RecentQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let history = state.history.read().await;
+    let count = query.count.unwrap_or(history.len()).min(history.len());
+    let mut snapshots: Vec<SystemSnapshot> = history
+        .iter()
+        .skip(history.len() - count)
+        .cloned()
+        .collect();
+    drop(history);
+
+    for snapshot in &mut snapshots {
+        redact(snapshot, &state.config.redact);
+        snapshot.truncate_to(state.config.max_snapshot_bytes);
+    }
+    Encoding::negotiate(&headers).respond(&snapshots)
+}
+
+// API endpoint rendering the latest snapshot in the Prometheus text
+// exposition format (0.0.4), for scraping instead of polling the JSON
+pub async fn get_prometheus_metrics(State(state): State<AppState>) -> Response {
+    let mut snapshot = state.latest_snapshot.borrow().clone();
+    redact(&mut snapshot, &state.config.redact);
+    // The hostname unless INSTANCE_LABEL is set, masked like everywhere else
+    // when REDACT_FIELDS hides it
+    let instance = state
+        .config
+        .instance_label
+        .as_deref()
+        .unwrap_or(&snapshot.hostname);
+    let body = prometheus_text(&snapshot, &state.config.metrics_prefix, instance);
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryCsvQuery {
+    seconds: Option<u64>,
+}
+
+// API endpoint downloading the buffered snapshots from the last `seconds`
+// (default: all of them) as a spreadsheet-friendly CSV file. Rows are
+// formatted as they are sent rather than building the whole file first.
+pub async fn get_history_csv(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryCsvQuery>,
+) -> Response {
+    let since = query.seconds.map_or(0, |seconds| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        now.saturating_sub(seconds.saturating_mul(1000))
+    });
+    let rows: Vec<HistoryCsvRow> = state
+        .history
+        .read()
+        .await
+        .iter()
+        .filter(|snapshot| snapshot.timestamp >= since)
+        .map(HistoryCsvRow::new)
+        .collect();
+
+    let lines = std::iter::once(HISTORY_CSV_HEADER.to_string())
+        .chain(rows.into_iter().map(|row| row.to_line()))
+        .map(Ok::<_, std::convert::Infallible>);
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"life_of_pi-history.csv\"",
+            ),
+        ],
+        axum::body::Body::from_stream(tokio_stream::iter(lines)),
+    )
+        .into_response()
+}
+
+// Server-Sent Events stream of snapshots as they are collected
+pub async fn stream_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    // The subscription is dropped together with the stream when the client
+    // disconnects. Clients that fall behind only see the latest snapshot.
+    let snapshots =
+        WatchStream::from_changes(state.latest_snapshot.clone()).map(move |mut snapshot| {
+            redact(&mut snapshot, &state.config.redact);
+            snapshot.truncate_to(state.config.max_snapshot_bytes);
+            Event::default().event("snapshot").json_data(snapshot)
+        });
+    Sse::new(snapshots).keep_alive(KeepAlive::default())
+}
+
+// API endpoint for service health
+// Responds 503 when unhealthy so orchestrators can act on the status code
+pub async fn get_health(State(state): State<AppState>) -> (StatusCode, Json<HealthStatus>) {
+    let collection_paused = state.collection_paused.load(Ordering::Relaxed);
+    let consecutive_collection_failures = state.collection_failures.load(Ordering::Relaxed);
+    let (mut failing_checks, sequence, falling_behind) = {
+        let snapshot = state.latest_snapshot.borrow();
+        let failing_checks = state.config.health_thresholds.evaluate(&snapshot);
+        (failing_checks, snapshot.sequence, snapshot.falling_behind)
+    };
+    let limit = state.config.max_consecutive_failures;
+    if limit > 0 && consecutive_collection_failures >= limit {
+        failing_checks.push(format!(
+            "collection stopped after {} failures in a row",
+            consecutive_collection_failures
+        ));
+    }
+    let (code, status) = if failing_checks.is_empty() {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unhealthy")
+    };
+    let health = HealthStatus {
+        status,
+        failing_checks,
+        sequence,
+        collection_paused,
+        consecutive_collection_failures,
+        falling_behind,
+        capabilities: (*state.capabilities).clone(),
+        warnings: state.capabilities.warnings(),
+    };
+    (code, Json(health))
+}
+
+// Control endpoints stopping and restarting collection, e.g. while running
+// intrusive diagnostics. The server and event streams stay up.
+pub async fn pause_collection(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    set_collection_paused(&state, &headers, true)
+}
+
+pub async fn resume_collection(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    set_collection_paused(&state, &headers, false)
+}
+
+fn set_collection_paused(state: &AppState, headers: &HeaderMap, paused: bool) -> Response {
+    if !is_authorized(state, headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if state.collection_paused.swap(paused, Ordering::Relaxed) != paused {
+        info!(
+            "Collection {} via the API",
+            if paused { "paused" } else { "resumed" }
+        );
+    }
+    Json(serde_json::json!({ "collection_paused": paused })).into_response()
+}
+
+// API endpoint for the effective configuration, when EXPOSE_CONFIG is set.
+// Requires the same bearer token as the control endpoints.
+pub async fn get_config(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if !is_authorized(&state, &headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(state.config.effective()).into_response()
+}
+
+// API endpoint describing the unit and type of each numeric snapshot field
+pub async fn get_units() -> Json<BTreeMap<&'static str, FieldUnit>> {
+    let units = FIELD_UNITS
+        .iter()
+        .map(|&(path, unit, value_type)| (path, FieldUnit { unit, value_type }))
+        .collect();
+    Json(units)
+}
+
+// Dashboard HTML
+pub async fn dashboard(State(state): State<AppState>) -> Response {
+    let html = match &state.dashboard_html {
+        Some(html) => match &*html.borrow() {
+            Ok(html) => html.clone(),
+            Err(e) => return (StatusCode::NOT_FOUND, e.clone()).into_response(),
+        },
+        None => match StaticAssets::get("index.html") {
+            Some(file) => String::from_utf8_lossy(&file.data).into_owned(),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+    };
+    // Let the page's script find the API when mounted under a prefix
+    Html(html.replace("__BASE_PATH__", &state.config.base_path)).into_response()
+}
+
+// Static files under /static
+pub async fn static_asset(Path(path): Path<String>) -> Response {
+    embedded_asset(&path)
+}
@@ -0,0 +1,381 @@
+//! HTTP server: shared state, routing and response helpers
+
+#[cfg(feature = "charts")]
+mod charts;
+mod handlers;
+
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post, Router},
+};
+use rust_embed::RustEmbed;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32},
+        Arc,
+    },
+    time::Duration,
+};
+use sysinfo::Networks;
+use tokio::{net::TcpListener, sync::watch};
+use tower_http::{cors::CorsLayer, services::ServeDir};
+use tracing::{info, warn};
+
+use crate::{
+    collector::Capabilities,
+    config::{Config, PortFallback},
+    snapshot::SystemSnapshot,
+};
+#[cfg(feature = "charts")]
+use charts::get_chart;
+use handlers::{
+    dashboard, get_config, get_health, get_history_csv, get_metrics, get_prometheus_metrics,
+    get_recent_snapshots, get_units, pause_collection, resume_collection, static_asset,
+    stream_events,
+};
+
+// Response for /api/health
+#[derive(Debug, Serialize)]
+struct HealthStatus {
+    status: &'static str, // "ok" or "unhealthy"
+    failing_checks: Vec<String>,
+    // Sequence number of the latest snapshot
+    sequence: u64,
+    // Collection is paused through /api/collection/pause
+    collection_paused: bool,
+    // Collections that failed since the last successful one
+    consecutive_collection_failures: u32,
+    // The latest snapshot came later than the configured interval allows
+    falling_behind: bool,
+    capabilities: Capabilities,
+    // Why some metrics are missing on this machine, see Capabilities::warnings
+    warnings: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub capabilities: Arc<Capabilities>,
+    // Latest snapshot, published by the collection task that owns the
+    // collector, so readers never contend with collection
+    pub latest_snapshot: watch::Receiver<SystemSnapshot>,
+    // Most recent snapshots, oldest first, capped at HISTORY_SIZE
+    pub history: Arc<tokio::sync::RwLock<VecDeque<SystemSnapshot>>>,
+    // Checked by the collection task on every tick
+    pub collection_paused: Arc<AtomicBool>,
+    // Collections that failed since the last successful one
+    pub collection_failures: Arc<AtomicU32>,
+    // STATIC_DIR/index.html as last loaded, or why it couldn't be read.
+    // None when serving the embedded dashboard.
+    pub dashboard_html: Option<watch::Receiver<Result<String, String>>>,
+}
+
+// Routes for every endpoint the configuration enables, under BASE_PATH
+pub fn router(config: &Config, state: AppState) -> Router {
+    let mut app = Router::new()
+        .route("/", get(dashboard))
+        .route("/api/metrics", get(get_metrics))
+        .route("/api/health", get(get_health))
+        .route("/api/events", get(stream_events))
+        .route("/api/snapshots/recent", get(get_recent_snapshots))
+        .route("/api/history.csv", get(get_history_csv))
+        .route("/api/units", get(get_units))
+        .route("/metrics", get(get_prometheus_metrics));
+    // Like the control endpoints, the configuration needs the CONTROL_TOKEN bearer
+    if config.expose_config {
+        app = app.route("/api/config", get(get_config));
+    }
+    if config.control_token.is_some() {
+        app = app
+            .route("/api/collection/pause", post(pause_collection))
+            .route("/api/collection/resume", post(resume_collection));
+    }
+    #[cfg(feature = "charts")]
+    {
+        app = app.route("/api/chart", get(get_chart));
+    }
+    app = match &config.static_dir {
+        Some(dir) => {
+            info!("Serving dashboard from {}", dir.display());
+            app.nest_service("/static", ServeDir::new(dir))
+        }
+        None => app.route("/static/*path", get(static_asset)),
+    };
+    if !config.base_path.is_empty() {
+        // A nested "/" only matches the bare prefix; proxies usually link the trailing slash
+        app = Router::new()
+            .route(&format!("{}/", config.base_path), get(dashboard))
+            .nest(&config.base_path, app);
+    }
+    app.layer(CorsLayer::permissive()).with_state(state)
+}
+
+// Bind the server's listener. When the port is taken, other ports are tried
+// as PORT_FALLBACK allows; the caller reads the bound address from the listener.
+pub async fn bind_listener(
+    ip: IpAddr,
+    port: u16,
+    fallback: PortFallback,
+) -> anyhow::Result<TcpListener> {
+    let in_use = |e: &std::io::Error| e.kind() == std::io::ErrorKind::AddrInUse;
+    let addr = SocketAddr::new(ip, port);
+    match TcpListener::bind(addr).await {
+        Ok(listener) => return Ok(listener),
+        Err(e) if in_use(&e) && fallback != PortFallback::Strict => {
+            warn!("Port {} is in use, trying PORT_FALLBACK={}", port, fallback)
+        }
+        Err(e) => anyhow::bail!("Failed to bind {}: {}", addr, e),
+    }
+
+    let candidates: Vec<u16> = match fallback {
+        PortFallback::Strict => Vec::new(),
+        PortFallback::Next(count) => (1..=count).filter_map(|i| port.checked_add(i)).collect(),
+        PortFallback::Ephemeral => vec![0],
+    };
+    for candidate in candidates {
+        let addr = SocketAddr::new(ip, candidate);
+        match TcpListener::bind(addr).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if in_use(&e) => continue,
+            Err(e) => anyhow::bail!("Failed to bind {}: {}", addr, e),
+        }
+    }
+    anyhow::bail!(
+        "Port {} and its PORT_FALLBACK={} ports are all in use",
+        port,
+        fallback
+    )
+}
+
+// Resolve the address of a named interface, preferring IPv4
+pub fn interface_address(name: &str) -> anyhow::Result<IpAddr> {
+    let networks = Networks::new_with_refreshed_list();
+    let network = networks
+        .list()
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("BIND_INTERFACE: no network interface named {:?}", name))?;
+
+    // Link-local IPv6 addresses need a scope id, which SocketAddr::new can't carry
+    let usable = |ip: &IpAddr| match ip {
+        IpAddr::V4(ipv4) => !ipv4.is_unspecified(),
+        IpAddr::V6(ipv6) => !ipv6.is_unspecified() && (ipv6.segments()[0] & 0xffc0) != 0xfe80,
+    };
+    let addresses: Vec<IpAddr> = network
+        .ip_networks()
+        .iter()
+        .map(|net| net.addr)
+        .filter(usable)
+        .collect();
+    addresses
+        .iter()
+        .find(|ip| ip.is_ipv4())
+        .or_else(|| addresses.first())
+        .copied()
+        .ok_or_else(|| {
+            anyhow::anyhow!("BIND_INTERFACE: interface {:?} has no usable address", name)
+        })
+}
+
+// Response encodings available for snapshots, chosen from the Accept header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Json,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Encoding {
+    // Pick the first acceptable media type we support, defaulting to JSON
+    fn negotiate(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Self::Json;
+        };
+        accept
+            .split(',')
+            .filter_map(|entry| {
+                let mut params = entry.split(';').map(str::trim);
+                let media_type = params.next()?;
+                // Skip types the client explicitly refuses with q=0
+                let refused =
+                    params.any(|p| p.strip_prefix("q=").is_some_and(|q| q.parse() == Ok(0.0)));
+                (!refused).then_some(media_type)
+            })
+            .find_map(Self::from_media_type)
+            .unwrap_or(Self::Json)
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Self::Json),
+            #[cfg(feature = "msgpack")]
+            "application/msgpack" | "application/x-msgpack" => Some(Self::MsgPack),
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    fn respond<T: Serialize>(self, value: &T) -> Response {
+        let encoded = match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "msgpack")]
+            Self::MsgPack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "cbor")]
+            Self::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf)
+                    .map(|()| buf)
+                    .map_err(|e| e.to_string())
+            }
+        };
+        match encoded {
+            Ok(body) => (
+                [
+                    (header::CONTENT_TYPE, self.media_type()),
+                    // The body depends on the Accept header, so caches must too
+                    (header::VARY, "Accept"),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        }
+    }
+
+    // Suffix telling the ETags of the encodings apart
+    fn name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            #[cfg(feature = "msgpack")]
+            Self::MsgPack => "msgpack",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "cbor",
+        }
+    }
+
+    fn media_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            #[cfg(feature = "msgpack")]
+            Self::MsgPack => "application/msgpack",
+            #[cfg(feature = "cbor")]
+            Self::Cbor => "application/cbor",
+        }
+    }
+}
+
+// Whether the request carries `Authorization: Bearer <CONTROL_TOKEN>`
+fn is_authorized(state: &AppState, headers: &HeaderMap) -> bool {
+    let Some(expected) = &state.config.control_token else {
+        return false;
+    };
+    let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Compare every byte so the response time doesn't reveal the matching prefix
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// Dashboard assets embedded into the binary
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct StaticAssets;
+
+// Load STATIC_DIR/index.html and keep reloading it in the background whenever
+// it changes, so an operator can swap in a customized dashboard without a
+// restart. Requests are served from memory in the meantime.
+pub async fn watch_dashboard(
+    dir: PathBuf,
+    debounce: Duration,
+) -> watch::Receiver<Result<String, String>> {
+    use notify::{RecursiveMode, Watcher};
+
+    let index = dir.join("index.html");
+    let read = |index: PathBuf| async move {
+        tokio::fs::read_to_string(&index)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", index.display(), e))
+    };
+    let (html_tx, html_rx) = watch::channel(read(index.clone()).await);
+
+    // Watch the directory rather than the file: editors often save by
+    // writing a new file and renaming it over the old one
+    let (changes_tx, mut changes_rx) = tokio::sync::mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let touches_index = event.is_ok_and(|event| {
+            event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == Some("index.html".as_ref()))
+        });
+        if touches_index {
+            let _ = changes_tx.send(());
+        }
+    })
+    .and_then(|mut watcher| {
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    let watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!(
+                "Can't watch {} for changes, restart to pick up dashboard edits: {}",
+                dir.display(),
+                e
+            );
+            return html_rx;
+        }
+    };
+
+    tokio::spawn(async move {
+        // Dropping the watcher stops it
+        let _watcher = watcher;
+        while changes_rx.recv().await.is_some() {
+            // Wait for the burst of events from one save to settle
+            loop {
+                match tokio::time::timeout(debounce, changes_rx.recv()).await {
+                    Ok(Some(())) => continue,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            let html = read(index.clone()).await;
+            match &html {
+                Ok(_) => info!("Reloaded {}", index.display()),
+                Err(e) => warn!("{}", e),
+            }
+            if html_tx.send(html).is_err() {
+                return; // server gone
+            }
+        }
+    });
+    html_rx
+}
+
+fn embedded_asset(path: &str) -> Response {
+    match StaticAssets::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype())],
+            file.data,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}